@@ -0,0 +1,381 @@
+/// Parser for nginx's predefined `combined` and `common` text log formats
+use chrono::{DateTime, FixedOffset};
+use std::borrow::Cow;
+use std::fmt::Display;
+
+use crate::nginx_log::NginxLogLine;
+
+/// Error returned when a line does not match the `combined` or `common` format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CombinedParseError {
+    /// Byte offset into the line where parsing failed
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for CombinedParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at column {}", self.message, self.column + 1)
+    }
+}
+
+impl std::error::Error for CombinedParseError {}
+
+/// Splits a text log line into its space separated fields
+///
+/// Fields wrapped in `[...]` or `"..."` are returned without their delimiters. Escape sequences
+/// are returned as logged, see [`unescape`], and an escaped `\"` does not end a quoted field.
+struct Fields<'a> {
+    line: &'a str,
+    pos: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(line: &'a str) -> Self {
+        Self { line, pos: 0 }
+    }
+
+    fn error(&self, message: &str) -> CombinedParseError {
+        CombinedParseError {
+            column: self.pos,
+            message: message.to_owned(),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        let rest = &self.line[self.pos..];
+        self.pos += rest.len() - rest.trim_start_matches(' ').len();
+    }
+
+    /// Returns the byte offset of the next field
+    fn column(&mut self) -> usize {
+        self.skip_spaces();
+        self.pos
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_spaces();
+        self.pos >= self.line.len()
+    }
+
+    /// Returns the next field, or an error naming `what` if the line ends early
+    fn next_field(&mut self, what: &str) -> Result<&'a str, CombinedParseError> {
+        if self.at_end() {
+            return Err(self.error(&format!("missing {}", what)));
+        }
+
+        let rest = &self.line[self.pos..];
+        match rest.as_bytes()[0] {
+            b'"' => {
+                let mut escaped = false;
+                for (i, c) in rest.char_indices().skip(1) {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => {
+                            self.pos += i + 1;
+                            return Ok(&rest[1..i]);
+                        }
+                        _ => {}
+                    }
+                }
+                Err(self.error(&format!("unterminated quote in {}", what)))
+            }
+            b'[' => match rest.find(']') {
+                Some(end) => {
+                    self.pos += end + 1;
+                    Ok(&rest[1..end])
+                }
+                None => Err(self.error(&format!("unterminated bracket in {}", what))),
+            },
+            _ => {
+                let end = rest.find(' ').unwrap_or(rest.len());
+                self.pos += end;
+                Ok(&rest[..end])
+            }
+        }
+    }
+
+    /// Returns the next field parsed as a number
+    fn next_number<T: std::str::FromStr>(&mut self, what: &str) -> Result<T, CombinedParseError> {
+        let start = self.column();
+        let field = self.next_field(what)?;
+        field.parse().map_err(|_| CombinedParseError {
+            column: start,
+            message: format!("invalid {} {:?}", what, field),
+        })
+    }
+}
+
+/// Parses a line in nginx's `combined` format, or the shorter `common` format
+///
+/// `combined` is `$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent
+/// "$http_referer" "$http_user_agent"`. `common` omits the referrer and user agent, which are
+/// then set to `-` as nginx would log them when empty. Escape sequences in the fields are decoded
+/// with [`unescape`], so they hold the same text as the fields of a JSON log.
+///
+/// # Arguments
+///
+/// * `line` - A single log line without its trailing newline
+///
+/// # Errors
+///
+/// Returns an error if the line does not match either format
+pub fn parse_line(line: &str) -> Result<NginxLogLine, CombinedParseError> {
+    let mut fields = Fields::new(line);
+
+    let remote_ip = unescape(fields.next_field("remote address")?).into_owned();
+    fields.next_field("ident")?;
+    let remote_user = unescape(fields.next_field("remote user")?).into_owned();
+    let time_column = fields.column();
+    let time = fields.next_field("time")?;
+    let time = crate::timestamp::parse(time).map_err(|e| CombinedParseError {
+        column: time_column,
+        message: e.to_string(),
+    })?;
+    let request = unescape(fields.next_field("request")?).into_owned();
+    let response = fields.next_number("status")?;
+    let bytes = fields.next_number("body bytes sent")?;
+
+    let (referrer, agent) = if fields.at_end() {
+        ("-".to_owned(), "-".to_owned())
+    } else {
+        let referrer = unescape(fields.next_field("referrer")?).into_owned();
+        let agent = unescape(fields.next_field("user agent")?).into_owned();
+        (referrer, agent)
+    };
+
+    Ok(NginxLogLine {
+        time,
+        remote_ip,
+        remote_user,
+        request,
+        response,
+        bytes,
+        referrer,
+        agent,
+        request_time: None,
+        upstream_response_time: None,
+        extra: Default::default(),
+    })
+}
+
+/// Decodes the escape sequences nginx writes into text log fields
+///
+/// nginx escapes `"`, `\`, control characters and bytes above 0x7E as `\xHH`, or with
+/// `escape=json` writes `\"` and `\\`. Decoded bytes that are not valid UTF-8 are replaced, and
+/// any other backslash is kept as written.
+///
+/// # Arguments
+///
+/// * `value` - A field as logged
+pub fn unescape(value: &str) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i..] {
+            [b'\\', b'x', high, low, ..] if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+                let hex = [high, low];
+                let hex = std::str::from_utf8(&hex).unwrap_or_default();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap_or_default());
+                i += 4;
+            }
+            [b'\\', escaped @ (b'"' | b'\\'), ..] => {
+                decoded.push(escaped);
+                i += 2;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+/// Formats a line in nginx's `combined` format
+///
+/// Values are escaped as nginx escapes them, writing `"`, `\`, control characters and bytes above
+/// 0x7E as `\xHH`, so [`parse_line`] reads back the same line.
+pub fn format_line(line: &NginxLogLine) -> String {
+    format!(
+        "{} - {} [{}] \"{}\" {} {} \"{}\" \"{}\"",
+        escape(&line.remote_ip),
+        escape(&line.remote_user),
+        crate::timestamp::format_local(&line.time),
+        escape(&line.request),
+        line.response,
+        line.bytes,
+        escape(&line.referrer),
+        escape(&line.agent)
+    )
+}
+
+/// Escapes a value as nginx does in text logs, the reverse of [`unescape`]
+fn escape(value: &str) -> Cow<'_, str> {
+    let needs_escape = |byte: u8| !(0x20..=0x7E).contains(&byte) || byte == b'"' || byte == b'\\';
+    if !value.bytes().any(needs_escape) {
+        return Cow::Borrowed(value);
+    }
+
+    let mut escaped = String::with_capacity(value.len() * 2);
+    for byte in value.bytes() {
+        if needs_escape(byte) {
+            escaped.push_str(&format!("\\x{:02X}", byte));
+        } else {
+            escaped.push(byte as char);
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Parses only the time of a line in the `combined` or `common` format
+///
+/// This is much cheaper than [`parse_line`], for deciding whether to parse the rest of the line.
+///
+/// # Arguments
+///
+/// * `line` - A single log line without its trailing newline
+///
+/// # Returns
+///
+/// The time of the line, or `None` if it cannot be parsed
+pub fn parse_time(line: &str) -> Option<DateTime<FixedOffset>> {
+    let mut fields = Fields::new(line);
+    for what in ["remote address", "ident", "remote user"] {
+        fields.next_field(what).ok()?;
+    }
+    crate::timestamp::parse(fields.next_field("time").ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_combined_and_common_lines() {
+        let line = parse_line(concat!(
+            r#"127.0.0.1 - frank [10/Oct/2026:13:55:36 +0200] "GET /a.gif HTTP/1.0" 200 2326 "#,
+            r#""http://www.example.com/start.html" "Mozilla/4.08 [en] (Win98; I ;Nav)""#
+        ))
+        .unwrap();
+        assert_eq!(line.remote_ip, "127.0.0.1");
+        assert_eq!(line.remote_user, "frank");
+        assert_eq!(line.time.to_rfc3339(), "2026-10-10T13:55:36+02:00");
+        assert_eq!(line.request, "GET /a.gif HTTP/1.0");
+        assert_eq!((line.response, line.bytes), (200, 2326));
+        assert_eq!(line.referrer, "http://www.example.com/start.html");
+        assert_eq!(line.agent, "Mozilla/4.08 [en] (Win98; I ;Nav)");
+
+        let common = parse_line(r#"::1 - - [10/Oct/2026:13:55:36 +0000] "GET / HTTP/1.1" 404 0"#);
+        let common = common.unwrap();
+        assert_eq!((common.response, common.bytes), (404, 0));
+        assert_eq!(
+            (common.referrer.as_str(), common.agent.as_str()),
+            ("-", "-")
+        );
+    }
+
+    #[test]
+    fn unescapes_quoted_fields() {
+        let line = parse_line(concat!(
+            r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "GET /caf\xC3\xA9 HTTP/1.1" 200 0 "#,
+            r#""-" "curl \x22quoted\x22 \"json\" \\ \x5C \d""#
+        ))
+        .unwrap();
+        assert_eq!(line.request, "GET /café HTTP/1.1");
+        assert_eq!(line.agent, r#"curl "quoted" "json" \ \ \d"#);
+
+        assert_eq!(unescape(r"\x16\x03\x01"), "\u{16}\u{3}\u{1}");
+        assert_eq!(unescape(r"\xFC!"), "\u{FFFD}!");
+        assert_eq!(unescape(r"\xZZ \x4"), r"\xZZ \x4");
+        assert!(matches!(unescape("plain"), Cow::Borrowed("plain")));
+    }
+
+    #[test]
+    fn reports_the_column_of_errors() {
+        let error = |line: &str| {
+            let error = parse_line(line).unwrap_err();
+            (error.column, error.message)
+        };
+
+        let (column, message) = error("10.0.0.1 - - [10/Oct/2026:13:55:36 +0000]");
+        assert_eq!((column, message.as_str()), (41, "missing request"));
+        let (column, message) = error(r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "GET /"#);
+        assert_eq!(
+            (column, message.as_str()),
+            (42, "unterminated quote in request")
+        );
+        let (column, message) = error(r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "-" 2x 0"#);
+        assert_eq!((column, message.as_str()), (46, r#"invalid status "2x""#));
+        let (column, _) = error(r#"10.0.0.1 - - [yesterday] "-" 200 0"#);
+        assert_eq!(column, 13);
+        let (column, message) = error(r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "-" 200 0 "-"#);
+        assert_eq!(
+            (column, message.as_str()),
+            (52, "unterminated quote in referrer")
+        );
+    }
+
+    #[test]
+    fn escapes_values_as_nginx_does() {
+        let line = NginxLogLine {
+            request: "GET /caf\u{e9}?q=\"a b\" HTTP/1.1".to_owned(),
+            referrer: r#"C:\dir \x22 \""#.to_owned(),
+            agent: "tab\tnewline\n\u{7f}".to_owned(),
+            ..parse_line(r#"::1 - - [10/Oct/2026:13:55:36 +0200] "-" 200 5"#).unwrap()
+        };
+        assert_eq!(
+            format_line(&line),
+            concat!(
+                r#"::1 - - [10/Oct/2026:13:55:36 +0200] "GET /caf\xC3\xA9?q=\x22a b\x22 HTTP/1.1" "#,
+                r#"200 5 "C:\x5Cdir \x5Cx22 \x5C\x22" "tab\x09newline\x0A\x7F""#
+            )
+        );
+    }
+
+    #[test]
+    fn formatted_lines_parse_back() {
+        let logged = [
+            concat!(
+                r#"127.0.0.1 - frank [10/Oct/2026:13:55:36 -0700] "GET /a.gif HTTP/1.0" 200 2326 "#,
+                r#""http://www.example.com/start.html" "Mozilla/4.08 [en] (Win98; I ;Nav)""#
+            ),
+            r#"::1 - - [10/Oct/2026:13:55:36 +0000] "GET / HTTP/1.1" 404 0"#,
+            concat!(
+                r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "\x16\x03\x01" 400 157 "#,
+                r#""-" "curl \x22quoted\x22 \"json\" \\ \x5C \d""#
+            ),
+        ];
+        let values = [
+            "",
+            "-",
+            "\"",
+            "\\",
+            "\\\"",
+            r"\x22",
+            r"\\x5C",
+            "[bracketed] \"quoted\"",
+            "caf\u{e9} \u{1F600}",
+            "\t\r\n\u{0}\u{7f}",
+        ];
+
+        let lines = logged.iter().map(|line| parse_line(line).unwrap());
+        let lines = lines.chain(values.iter().map(|value| NginxLogLine {
+            request: format!("GET /{} HTTP/1.1", value),
+            referrer: value.to_string(),
+            agent: value.repeat(2),
+            ..parse_line(logged[0]).unwrap()
+        }));
+        for line in lines {
+            let formatted = format_line(&line);
+            assert_eq!(parse_line(&formatted), Ok(line), "{}", formatted);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+/// Transparent decompression of rotated log files
+use std::io::{BufRead, BufReader, Cursor, ErrorKind, Read};
+
+/// A compression format, identified by the magic bytes at the start of a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Zstd,
+}
+
+impl Compression {
+    /// Identifies the compression format from the first bytes of a stream
+    ///
+    /// # Arguments
+    ///
+    /// * `magic` - At least the first four bytes of the stream, if it has that many
+    pub fn detect(magic: &[u8]) -> Self {
+        match magic {
+            [0x1f, 0x8b, ..] => Self::Gzip,
+            [b'B', b'Z', b'h', ..] => Self::Bzip2,
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+}
+
+/// Wraps a reader so that compressed data is decompressed as it is read
+///
+/// The compression format is detected from the magic bytes at the start of the stream, and
+/// uncompressed data is passed through unchanged. Concatenated gzip members, bzip2 streams and
+/// zstd frames are all read to the end.
+///
+/// # Arguments
+///
+/// * `reader` - The reader to decompress
+///
+/// # Errors
+///
+/// Returns an error if the start of the stream cannot be read
+pub fn decompress<R>(mut reader: R) -> std::io::Result<Box<dyn BufRead + Send>>
+where
+    R: BufRead + Send + 'static,
+{
+    // A single fill_buf may return fewer than 4 bytes (e.g. from a pipe), so read the magic bytes
+    // out and put them back in front of the rest of the stream
+    let mut magic = [0; 4];
+    let mut len = 0;
+    while len < magic.len() {
+        match reader.read(&mut magic[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let compression = Compression::detect(&magic[..len]);
+    let reader = Cursor::new(magic[..len].to_vec()).chain(reader);
+
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        Compression::Bzip2 => Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(
+            reader,
+        )?)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const LOG: &[u8] = b"first line\nsecond line\n";
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn bzip2(data: &[u8]) -> Vec<u8> {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zstd(data: &[u8]) -> Vec<u8> {
+        zstd::encode_all(data, 0).unwrap()
+    }
+
+    fn read(data: Vec<u8>) -> Vec<u8> {
+        let mut decompressed = Vec::new();
+        decompress(Cursor::new(data))
+            .unwrap()
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        decompressed
+    }
+
+    #[test]
+    fn decompresses_every_format() {
+        for (compress, compression) in [
+            (gzip as fn(&[u8]) -> Vec<u8>, Compression::Gzip),
+            (bzip2, Compression::Bzip2),
+            (zstd, Compression::Zstd),
+        ] {
+            let compressed = compress(LOG);
+            assert_eq!(Compression::detect(&compressed), compression);
+            assert_eq!(read(compressed), LOG);
+
+            let mut concatenated = compress(b"first line\n");
+            concatenated.extend(compress(b"second line\n"));
+            assert_eq!(read(concatenated), LOG, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn passes_uncompressed_data_through() {
+        assert_eq!(Compression::detect(LOG), Compression::None);
+        assert_eq!(read(LOG.to_vec()), LOG);
+        assert_eq!(read(Vec::new()), b"");
+        assert_eq!(read(vec![0x1f]), [0x1f]);
+    }
+
+    /// A reader that returns at most one byte per read, like a slow pipe
+    struct OneByteAtATime(Cursor<Vec<u8>>);
+
+    impl Read for OneByteAtATime {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn detects_magic_bytes_split_across_reads() {
+        for data in [
+            gzip(LOG),
+            bzip2(LOG),
+            zstd(LOG),
+            LOG.to_vec(),
+            b"ab".to_vec(),
+        ] {
+            let expected = if Compression::detect(&data) == Compression::None {
+                data.clone()
+            } else {
+                LOG.to_vec()
+            };
+            let reader = BufReader::with_capacity(1, OneByteAtATime(Cursor::new(data)));
+            let mut decompressed = Vec::new();
+            decompress(reader)
+                .unwrap()
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, expected);
+        }
+    }
+}
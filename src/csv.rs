@@ -0,0 +1,96 @@
+/// Formatting of log lines as CSV
+use std::borrow::Cow;
+
+use crate::nginx_log::NginxLogLine;
+
+/// The header row naming the columns of [`format_line`]
+pub const HEADER: &str =
+    "time,remote_ip,remote_user,request,response,bytes,referrer,agent,request_time,upstream_response_time";
+
+/// Formats a line as a CSV row, with the columns of [`HEADER`]
+///
+/// Times are written as `$time_iso8601`, timings in seconds or empty if the line has none, and
+/// values are quoted as RFC 4180 describes when needed. Values in [`NginxLogLine::extra`] are
+/// left out, since lines can have different ones.
+pub fn format_line(line: &NginxLogLine) -> String {
+    let timing = |time: Option<std::time::Duration>| {
+        time.map(|time| time.as_secs_f64().to_string())
+            .unwrap_or_default()
+    };
+
+    [
+        Cow::Owned(line.time.to_rfc3339()),
+        escape(&line.remote_ip),
+        escape(&line.remote_user),
+        escape(&line.request),
+        Cow::Owned(line.response.to_string()),
+        Cow::Owned(line.bytes.to_string()),
+        escape(&line.referrer),
+        escape(&line.agent),
+        Cow::Owned(timing(line.request_time)),
+        Cow::Owned(timing(line.upstream_response_time)),
+    ]
+    .join(",")
+}
+
+/// Quotes a value if it contains a comma, a double quote or a line break
+fn escape(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn line() -> NginxLogLine {
+        crate::combined::parse_line(concat!(
+            r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0200] "GET /a HTTP/1.1" 200 512 "#,
+            r#""https://example.com/" "curl/8.0""#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn formats_a_row_per_line() {
+        let mut line = line();
+        assert_eq!(
+            format_line(&line),
+            "2026-10-10T13:55:36+02:00,10.0.0.1,-,GET /a HTTP/1.1,200,512,https://example.com/,curl/8.0,,"
+        );
+
+        line.request_time = Some(Duration::from_millis(1250));
+        line.upstream_response_time = Some(Duration::from_secs(1));
+        line.extra
+            .insert("host".to_owned(), "example.com".to_owned());
+        let row = format_line(&line);
+        assert!(row.ends_with(",curl/8.0,1.25,1"), "{}", row);
+        assert_eq!(row.split(',').count(), HEADER.split(',').count());
+    }
+
+    #[test]
+    fn quotes_values_when_needed() {
+        let line = NginxLogLine {
+            request: r#"GET /search?q="a,b" HTTP/1.1"#.to_owned(),
+            referrer: "multi\nline\r".to_owned(),
+            agent: "Mozilla/5.0 (X11; Linux x86_64) \"quoted\\\"".to_owned(),
+            ..line()
+        };
+        assert_eq!(
+            format_line(&line),
+            concat!(
+                r#"2026-10-10T13:55:36+02:00,10.0.0.1,-,"GET /search?q=""a,b"" HTTP/1.1",200,512,"#,
+                "\"multi\nline\r\",",
+                r#""Mozilla/5.0 (X11; Linux x86_64) ""quoted\""",,"#
+            )
+        );
+
+        assert_eq!(escape("plain text"), "plain text");
+        assert_eq!(escape(""), "");
+        assert_eq!(escape(r#"say "hi""#), r#""say ""hi""""#);
+    }
+}
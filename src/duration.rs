@@ -0,0 +1,228 @@
+/// Parsing of the request timings nginx can write to its logs
+use serde::de::{self, Visitor};
+use serde::{Deserializer, Serializer};
+use std::fmt::Display;
+use std::time::Duration;
+
+/// Error returned when a value is not a timing nginx writes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DurationParseError {
+    pub value: String,
+}
+
+impl Display for DurationParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid duration {:?}, expected seconds such as 0.123 or -",
+            self.value
+        )
+    }
+}
+
+impl std::error::Error for DurationParseError {}
+
+/// Parses a timing written by nginx, such as `$request_time` or `$upstream_response_time`
+///
+/// Timings are seconds with an optional fraction, like `0.123`. `$upstream_response_time` lists
+/// one timing per upstream server tried, separated by `, ` between servers and ` : ` between
+/// upstream groups after an internal redirect, and those timings are added up since the servers
+/// were tried one after another. `-` stands for a request that never reached an upstream.
+///
+/// # Arguments
+///
+/// * `value` - The timing as written in the log
+///
+/// # Returns
+///
+/// The total duration, or `None` if every timing is `-`
+///
+/// # Errors
+///
+/// Returns an error if any of the timings is not a number of seconds or `-`, or if their total
+/// is too long for a [`Duration`]
+pub fn parse(value: &str) -> Result<Option<Duration>, DurationParseError> {
+    let error = || DurationParseError {
+        value: value.to_owned(),
+    };
+
+    let mut total = None;
+    for timing in value.split([',', ':']).map(str::trim) {
+        if timing == "-" {
+            continue;
+        }
+        let timing = parse_seconds(timing).ok_or_else(error)?;
+        let sum = total.unwrap_or(Duration::ZERO).checked_add(timing);
+        total = Some(sum.ok_or_else(error)?);
+    }
+
+    Ok(total)
+}
+
+/// Parses seconds with an optional fraction, like `$msec` without the epoch
+fn parse_seconds(value: &str) -> Option<Duration> {
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if seconds.is_empty()
+        || !seconds.bytes().all(|b| b.is_ascii_digit())
+        || fraction.len() > 9
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let seconds = seconds.parse::<u64>().ok()?;
+    let nanos = format!("{:0<9}", fraction).parse::<u32>().ok()?;
+    Some(Duration::new(seconds, nanos))
+}
+
+/// Serializes a timing as a number of seconds, or `null` for `None`
+pub fn serialize<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => serializer.serialize_f64(duration.as_secs_f64()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Deserializes a timing from a JSON string or number, see [`parse`]
+///
+/// `null` and `"-"` are deserialized as `None`.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct DurationVisitor;
+
+    impl Visitor<'_> for DurationVisitor {
+        type Value = Option<Duration>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("an nginx $request_time or $upstream_response_time")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            parse(value).map_err(E::custom)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+            Ok(Some(Duration::from_secs(value)))
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+            self.visit_str(&value.to_string())
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+            Duration::try_from_secs_f64(value).map(Some).map_err(|_| {
+                E::custom(DurationParseError {
+                    value: value.to_string(),
+                })
+            })
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+    }
+
+    deserializer.deserialize_any(DurationVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    fn millis(millis: u64) -> Result<Option<Duration>, DurationParseError> {
+        Ok(Some(Duration::from_millis(millis)))
+    }
+
+    fn invalid(value: &str) -> Result<Option<Duration>, DurationParseError> {
+        Err(DurationParseError {
+            value: value.to_owned(),
+        })
+    }
+
+    #[test]
+    fn parses_seconds() {
+        assert_eq!(parse("0.123"), millis(123));
+        assert_eq!(parse("12"), millis(12_000));
+        assert_eq!(parse("1."), millis(1_000));
+        assert_eq!(parse("0.000000001"), Ok(Some(Duration::from_nanos(1))));
+        assert_eq!(parse("-"), Ok(None));
+
+        for value in [
+            "",
+            ".5",
+            "0.1234567890",
+            "1e3",
+            "-1",
+            "0.12s",
+            "1 2",
+            "0x10",
+        ] {
+            assert_eq!(parse(value), invalid(value));
+        }
+    }
+
+    #[test]
+    fn adds_up_upstream_timings() {
+        assert_eq!(parse("0.100, 0.020"), millis(120));
+        assert_eq!(parse("0.100, 0.020 : 0.003"), millis(123));
+        assert_eq!(parse("-, 0.5"), millis(500));
+        assert_eq!(parse("- : -"), Ok(None));
+        assert_eq!(parse("0.5,"), invalid("0.5,"));
+
+        let huge = "18446744073709551615, 18446744073709551615";
+        assert_eq!(parse(huge), invalid(huge));
+        assert_eq!(
+            invalid(huge).unwrap_err().to_string(),
+            format!(
+                "invalid duration {:?}, expected seconds such as 0.123 or -",
+                huge
+            )
+        );
+    }
+
+    #[test]
+    fn deserializes_strings_numbers_and_null() {
+        #[derive(Deserialize)]
+        struct Line {
+            #[serde(deserialize_with = "deserialize")]
+            time: Option<Duration>,
+        }
+        let time = |json: &str| serde_json::from_str::<Line>(json).map(|line| line.time);
+
+        assert_eq!(time(r#"{"time": "0.250"}"#).unwrap(), millis(250).unwrap());
+        assert_eq!(
+            time(r#"{"time": "0.1, 0.2"}"#).unwrap(),
+            millis(300).unwrap()
+        );
+        assert_eq!(time(r#"{"time": "-"}"#).unwrap(), None);
+        assert_eq!(time(r#"{"time": null}"#).unwrap(), None);
+        assert_eq!(time(r#"{"time": 2}"#).unwrap(), millis(2_000).unwrap());
+        assert_eq!(time(r#"{"time": 0.25}"#).unwrap(), millis(250).unwrap());
+
+        for json in [
+            r#"{"time": -1}"#,
+            r#"{"time": -0.5}"#,
+            r#"{"time": "soon"}"#,
+            r#"{"time": 1e300}"#,
+        ] {
+            assert!(time(json).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn serializes_seconds() {
+        let json = |duration: Option<Duration>| {
+            let mut json = Vec::new();
+            serialize(&duration, &mut serde_json::Serializer::new(&mut json)).unwrap();
+            String::from_utf8(json).unwrap()
+        };
+        assert_eq!(json(Some(Duration::from_millis(1500))), "1.5");
+        assert_eq!(json(None), "null");
+    }
+}
@@ -0,0 +1,326 @@
+/// Per-endpoint breakdown of LogStats
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::stats::{RequestStats, Summary};
+use crate::table;
+
+/// Statistics about the requests to a single endpoint
+///
+/// # Contains
+///
+/// * `endpoint` - The endpoint
+/// * `stats` - Statistics about the requests made to it, flattened into the same JSON object
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EndpointStats {
+    pub endpoint: String,
+    #[serde(flatten)]
+    pub stats: RequestStats,
+}
+
+/// A column of the endpoint table that endpoints can be sorted by
+///
+/// Parsed from the lowercase column name with spaces replaced by `_`, such as `requests`,
+/// `error_rate`, `5xx`, `bytes_p99` or `time_mean`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EndpointColumn {
+    Endpoint,
+    Requests,
+    Errors,
+    ErrorRate,
+    /// Responses with a status code in the given hundred, such as `5` for `5xx`
+    StatusClass(u16),
+    Bytes(Statistic),
+    RequestTime(Statistic),
+    UpstreamResponseTime(Statistic),
+}
+
+/// A statistic of a [`Summary`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Statistic {
+    Mean,
+    Percentile(f64),
+}
+
+/// Error returned when parsing an unknown [`EndpointColumn`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownColumn {
+    pub column: String,
+}
+
+impl Display for UnknownColumn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unknown column {:?}, expected endpoint, requests, errors, error_rate, 1xx to 5xx, or \
+             bytes, time or upstream followed by _mean or a percentile such as _p99",
+            self.column
+        )
+    }
+}
+
+impl std::error::Error for UnknownColumn {}
+
+impl FromStr for EndpointColumn {
+    type Err = UnknownColumn;
+
+    fn from_str(column: &str) -> Result<Self, Self::Err> {
+        let error = || UnknownColumn {
+            column: column.to_owned(),
+        };
+
+        match column {
+            "endpoint" => return Ok(Self::Endpoint),
+            "requests" => return Ok(Self::Requests),
+            "errors" => return Ok(Self::Errors),
+            "error_rate" => return Ok(Self::ErrorRate),
+            _ => {}
+        }
+
+        if let Some(class) = column.strip_suffix("xx") {
+            return match class.parse() {
+                Ok(class @ 1..=5) => Ok(Self::StatusClass(class)),
+                _ => Err(error()),
+            };
+        }
+
+        let (metric, statistic) = column.rsplit_once('_').ok_or_else(error)?;
+        let statistic = if statistic == "mean" {
+            Statistic::Mean
+        } else {
+            let percentile = statistic
+                .strip_prefix('p')
+                .and_then(|percentile| percentile.parse::<f64>().ok())
+                .filter(|percentile| (0.0..=100.0).contains(percentile))
+                .ok_or_else(error)?;
+            Statistic::Percentile(percentile)
+        };
+
+        match metric {
+            "bytes" => Ok(Self::Bytes(statistic)),
+            "time" => Ok(Self::RequestTime(statistic)),
+            "upstream" => Ok(Self::UpstreamResponseTime(statistic)),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl EndpointColumn {
+    /// Returns the percentile the column needs to have been computed, if any
+    pub fn percentile(&self) -> Option<f64> {
+        match self {
+            Self::Bytes(Statistic::Percentile(percentile))
+            | Self::RequestTime(Statistic::Percentile(percentile))
+            | Self::UpstreamResponseTime(Statistic::Percentile(percentile)) => Some(*percentile),
+            _ => None,
+        }
+    }
+
+    /// Returns the numeric value of the column for an endpoint
+    ///
+    /// `None` for [`EndpointColumn::Endpoint`] and for statistics the endpoint does not have.
+    pub fn value(&self, endpoint: &EndpointStats) -> Option<f64> {
+        let stats = &endpoint.stats;
+        let statistic = |summary: Option<&Summary>, statistic: &Statistic| {
+            let summary = summary?;
+            match statistic {
+                Statistic::Mean => Some(summary.mean),
+                Statistic::Percentile(percentile) => summary.percentile(*percentile),
+            }
+        };
+
+        match self {
+            Self::Endpoint => None,
+            Self::Requests => Some(stats.requests as f64),
+            Self::Errors => Some(stats.errors as f64),
+            Self::ErrorRate => Some(stats.error_rate),
+            Self::StatusClass(class) => Some(
+                stats
+                    .status_classes
+                    .get(&format!("{}xx", class))
+                    .copied()
+                    .unwrap_or(0) as f64,
+            ),
+            Self::Bytes(s) => statistic(Some(&stats.bytes), s),
+            Self::RequestTime(s) => statistic(stats.request_time.as_ref(), s),
+            Self::UpstreamResponseTime(s) => statistic(stats.upstream_response_time.as_ref(), s),
+        }
+    }
+}
+
+/// Sorts endpoints by a column
+///
+/// Endpoints are sorted alphabetically for [`EndpointColumn::Endpoint`], and from the highest
+/// value to the lowest for every other column, with endpoints missing the value last. Ties are
+/// broken alphabetically.
+///
+/// # Arguments
+///
+/// * `endpoints` - The endpoints to sort
+/// * `column` - The column to sort by
+pub fn sort(endpoints: &mut [EndpointStats], column: &EndpointColumn) {
+    endpoints.sort_by(|a, b| {
+        let by_value = match (column.value(a), column.value(b)) {
+            (Some(a), Some(b)) => b.total_cmp(&a),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        by_value.then_with(|| a.endpoint.cmp(&b.endpoint))
+    });
+}
+
+/// A text table of endpoint stats, one row per endpoint in the given order
+pub struct EndpointTable<'a>(pub &'a [EndpointStats]);
+
+impl Display for EndpointTable<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let columns = table::Columns::of(self.0.iter().map(|endpoint| &endpoint.stats));
+
+        let mut header = vec!["Endpoint".to_owned()];
+        header.extend(columns.header());
+        let mut rows = vec![header];
+        for endpoint in self.0 {
+            let mut row = vec![endpoint.endpoint.clone()];
+            row.extend(columns.cells(&endpoint.stats));
+            rows.push(row);
+        }
+
+        table::write(f, &rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::PercentileValue;
+
+    fn summary(mean: f64, p99: f64) -> Summary {
+        Summary {
+            count: 1,
+            mean,
+            percentiles: vec![PercentileValue {
+                percentile: 99.0,
+                value: p99,
+            }],
+        }
+    }
+
+    fn endpoint(endpoint: &str, requests: usize, request_time: Option<Summary>) -> EndpointStats {
+        EndpointStats {
+            endpoint: endpoint.to_owned(),
+            stats: RequestStats {
+                requests,
+                errors: 0,
+                error_rate: 0.0,
+                status_classes: [("2xx".to_owned(), requests)].into_iter().collect(),
+                bytes: summary(100.0, 200.0),
+                request_time,
+                upstream_response_time: None,
+            },
+        }
+    }
+
+    fn names(endpoints: &[EndpointStats]) -> Vec<&str> {
+        endpoints
+            .iter()
+            .map(|endpoint| endpoint.endpoint.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn parses_columns() {
+        assert_eq!("endpoint".parse(), Ok(EndpointColumn::Endpoint));
+        assert_eq!("error_rate".parse(), Ok(EndpointColumn::ErrorRate));
+        assert_eq!("5xx".parse(), Ok(EndpointColumn::StatusClass(5)));
+        assert_eq!(
+            "bytes_p99.9".parse(),
+            Ok(EndpointColumn::Bytes(Statistic::Percentile(99.9)))
+        );
+        assert_eq!(
+            "time_mean".parse(),
+            Ok(EndpointColumn::RequestTime(Statistic::Mean))
+        );
+        assert_eq!(
+            "upstream_p50".parse(),
+            Ok(EndpointColumn::UpstreamResponseTime(Statistic::Percentile(
+                50.0
+            )))
+        );
+        assert_eq!(
+            "time_p99".parse::<EndpointColumn>().unwrap().percentile(),
+            Some(99.0)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_columns() {
+        for column in [
+            "",
+            "method",
+            "6xx",
+            "0xx",
+            "bytes",
+            "bytes_p101",
+            "bytes_pfast",
+            "bytes_median",
+            "latency_p99",
+        ] {
+            assert_eq!(
+                column.parse::<EndpointColumn>(),
+                Err(UnknownColumn {
+                    column: column.to_owned()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn sorts_by_endpoint_alphabetically() {
+        let mut endpoints = vec![endpoint("/b", 1, None), endpoint("/a", 2, None)];
+        sort(&mut endpoints, &EndpointColumn::Endpoint);
+        assert_eq!(names(&endpoints), ["/a", "/b"]);
+    }
+
+    #[test]
+    fn sorts_values_highest_first_with_missing_values_last() {
+        let mut endpoints = vec![
+            endpoint("/untimed", 5, None),
+            endpoint("/slow", 1, Some(summary(2.0, 3.0))),
+            endpoint("/fast", 3, Some(summary(0.1, 0.2))),
+            endpoint("/also-fast", 3, Some(summary(0.1, 0.2))),
+        ];
+
+        sort(&mut endpoints, &"requests".parse().unwrap());
+        assert_eq!(
+            names(&endpoints),
+            ["/untimed", "/also-fast", "/fast", "/slow"]
+        );
+
+        sort(&mut endpoints, &"time_p99".parse().unwrap());
+        assert_eq!(
+            names(&endpoints),
+            ["/slow", "/also-fast", "/fast", "/untimed"]
+        );
+
+        sort(&mut endpoints, &"time_p50".parse().unwrap());
+        assert_eq!(
+            names(&endpoints),
+            ["/also-fast", "/fast", "/slow", "/untimed"]
+        );
+    }
+
+    #[test]
+    fn sizes_columns_by_characters() {
+        let endpoints = [endpoint("/café/crème-brûlée", 1, None)];
+        let table = EndpointTable(&endpoints).to_string();
+        let row = table.lines().nth(1).unwrap();
+        assert!(
+            row.starts_with("  /café/crème-brûlée         1  "),
+            "{}",
+            table
+        );
+    }
+}
@@ -0,0 +1,791 @@
+/// Filter expressions selecting which log lines to read
+use regex::Regex;
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::nginx_log::NginxLogLine;
+
+/// Error returned when a filter expression cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterParseError {
+    /// Byte offset into the expression where the error was found
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for FilterParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at column {}", self.message, self.column + 1)
+    }
+}
+
+impl std::error::Error for FilterParseError {}
+
+/// A value of a log line that can be filtered on
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Status,
+    Bytes,
+    RequestTime,
+    UpstreamTime,
+    Ip,
+    User,
+    Method,
+    Path,
+    Request,
+    Referrer,
+    Agent,
+    /// Any other `log_format` variable or JSON key, from [`NginxLogLine::extra`]
+    Variable(String),
+}
+
+/// The names fields can be written with, for parsing and error messages
+const FIELDS: [(&str, Field); 13] = [
+    ("status", Field::Status),
+    ("bytes", Field::Bytes),
+    ("request_time", Field::RequestTime),
+    ("upstream_time", Field::UpstreamTime),
+    ("upstream_response_time", Field::UpstreamTime),
+    ("ip", Field::Ip),
+    ("user", Field::User),
+    ("method", Field::Method),
+    ("path", Field::Path),
+    ("request", Field::Request),
+    ("referrer", Field::Referrer),
+    ("referer", Field::Referrer),
+    ("agent", Field::Agent),
+];
+
+/// The value of a [`Field`] on a line
+enum Value<'a> {
+    Number(f64),
+    Text(Cow<'a, str>),
+}
+
+impl Value<'_> {
+    /// Returns the value as a number, parsing text values, or `None` if it is not one
+    fn number(&self) -> Option<f64> {
+        match self {
+            Self::Number(number) => Some(*number),
+            Self::Text(text) => text.parse().ok(),
+        }
+    }
+
+    /// Returns the value as text, formatting numbers as they would be logged
+    fn text(&self) -> Cow<'_, str> {
+        match self {
+            Self::Number(number) => Cow::Owned(number.to_string()),
+            Self::Text(text) => Cow::Borrowed(text),
+        }
+    }
+}
+
+impl Field {
+    /// Returns the value of the field on a line, or `None` if the line does not have it
+    fn value<'a>(&self, line: &'a NginxLogLine) -> Option<Value<'a>> {
+        let text = |text: &'a str| Some(Value::Text(Cow::Borrowed(text)));
+        match self {
+            Self::Status => Some(Value::Number(line.response.into())),
+            Self::Bytes => Some(Value::Number(line.bytes as f64)),
+            Self::RequestTime => line.request_time.map(|t| Value::Number(t.as_secs_f64())),
+            Self::UpstreamTime => line
+                .upstream_response_time
+                .map(|t| Value::Number(t.as_secs_f64())),
+            Self::Ip => text(&line.remote_ip),
+            Self::User => text(&line.remote_user),
+            Self::Method => line
+                .request_line()
+                .ok()
+                .map(|request| Value::Text(Cow::Owned(request.method.as_str().to_owned()))),
+            Self::Path => line
+                .request_line()
+                .ok()
+                .map(|request| Value::Text(Cow::Owned(request.path))),
+            Self::Request => text(&line.request),
+            Self::Referrer => text(&line.referrer),
+            Self::Agent => text(&line.agent),
+            Self::Variable(name) => line.extra.get(name).and_then(|value| text(value)),
+        }
+    }
+}
+
+/// An IP network in CIDR notation, like `10.0.0.0/8` or `2001:db8::/32`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Network {
+    address: IpAddr,
+    prefix: u32,
+}
+
+impl Network {
+    /// Parses a network, or a single address as a network of one
+    fn parse(network: &str) -> Option<Self> {
+        let (address, prefix) = match network.split_once('/') {
+            Some((address, prefix)) => (address.parse::<IpAddr>().ok()?, Some(prefix)),
+            None => (network.parse::<IpAddr>().ok()?, None),
+        };
+        let bits = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u32>().ok().filter(|p| *p <= bits)?,
+            None => bits,
+        };
+        Some(Self { address, prefix })
+    }
+
+    fn contains(&self, address: &IpAddr) -> bool {
+        let mask = |bits: u32| u128::MAX.checked_shl(bits - self.prefix).unwrap_or(0);
+        match (self.address, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = mask(32) as u32;
+                u32::from(network) & mask == u32::from(*address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = mask(128);
+                u128::from(network) & mask == u128::from(*address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A comparison operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A test of a single field of a line
+#[derive(Debug, Clone)]
+enum Condition {
+    /// Compares the field as a number
+    Number(Comparison, f64),
+    /// Compares the field as text, only `==` and `!=`
+    Text(Comparison, String),
+    Matches(Regex),
+    NotMatches(Regex),
+    Contains(String),
+    StartsWith(String),
+    EndsWith(String),
+    In(Network),
+}
+
+impl Condition {
+    fn test(&self, value: &Value) -> bool {
+        let compare = |comparison: &Comparison, ordering: std::cmp::Ordering| match comparison {
+            Comparison::Eq => ordering.is_eq(),
+            Comparison::Ne => ordering.is_ne(),
+            Comparison::Lt => ordering.is_lt(),
+            Comparison::Le => ordering.is_le(),
+            Comparison::Gt => ordering.is_gt(),
+            Comparison::Ge => ordering.is_ge(),
+        };
+
+        match self {
+            Self::Number(comparison, number) => value
+                .number()
+                .and_then(|value| value.partial_cmp(number))
+                .is_some_and(|ordering| compare(comparison, ordering)),
+            Self::Text(comparison, text) => compare(comparison, value.text().as_ref().cmp(text)),
+            Self::Matches(regex) => regex.is_match(&value.text()),
+            Self::NotMatches(regex) => !regex.is_match(&value.text()),
+            Self::Contains(text) => value.text().contains(text.as_str()),
+            Self::StartsWith(text) => value.text().starts_with(text.as_str()),
+            Self::EndsWith(text) => value.text().ends_with(text.as_str()),
+            Self::In(network) => value
+                .text()
+                .parse::<IpAddr>()
+                .is_ok_and(|address| network.contains(&address)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Test(Field, Condition),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    fn matches(&self, line: &NginxLogLine) -> bool {
+        match self {
+            Self::Test(field, condition) => field
+                .value(line)
+                .is_some_and(|value| condition.test(&value)),
+            Self::Not(expr) => !expr.matches(line),
+            Self::And(exprs) => exprs.iter().all(|expr| expr.matches(line)),
+            Self::Or(exprs) => exprs.iter().any(|expr| expr.matches(line)),
+        }
+    }
+}
+
+/// A compiled filter expression, selecting log lines by their fields
+///
+/// An expression is made of tests like `status >= 500` combined with `&&`, `||`, `!` and
+/// parentheses, where `&&` binds tighter than `||`. Each test compares a field with a value:
+///
+/// | Operator                        | Value                                                   |
+/// |---------------------------------|---------------------------------------------------------|
+/// | `==`, `!=`                      | a number for numeric fields, text otherwise             |
+/// | `<`, `<=`, `>`, `>=`            | a number                                                |
+/// | `~`, `!~`                       | a regex the field matches, or does not, anywhere        |
+/// | `contains`, `startswith`, `endswith` | text                                               |
+/// | `in`                            | an IP network like `10.0.0.0/8`, or a single address    |
+///
+/// The fields are `status`, `bytes`, `request_time` and `upstream_time` in seconds, `ip`, `user`,
+/// `method`, `path` (decoded, without the query string), `request`, `referrer` and `agent`, or
+/// `$name` for any other `log_format` variable or JSON key. Text values are written in double
+/// quotes, with `\"` and `\\` escapes, or without quotes if they have no spaces or operators.
+/// Tests on a field the line does not have, such as `upstream_time` for a request that was not
+/// passed upstream or `method` for a malformed request, are false.
+///
+/// For example `status >= 500 && path ~ "^/api/" && ip in 10.0.0.0/8`.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    /// Returns whether a line matches the filter
+    pub fn matches(&self, line: &NginxLogLine) -> bool {
+        self.expr.matches(line)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = FilterParseError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            pos: 0,
+            end: expression.len(),
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(token.error(match token.kind {
+                TokenKind::Symbol(")") => "unmatched ')'".to_owned(),
+                _ => format!("expected && or || before {}", token.describe()),
+            }));
+        }
+        Ok(Self { expr })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    /// A field name, word operator or unquoted value
+    Word(String),
+    /// A quoted value, with its escapes resolved
+    Quoted(String),
+    Symbol(&'static str),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: String) -> FilterParseError {
+        FilterParseError {
+            column: self.column,
+            message,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match &self.kind {
+            TokenKind::Word(word) => format!("{:?}", word),
+            TokenKind::Quoted(text) => format!("{:?}", text),
+            TokenKind::Symbol(symbol) => format!("'{}'", symbol),
+        }
+    }
+}
+
+/// Symbols, longest first so that `<=` is not read as `<` followed by `=`
+const SYMBOLS: [&str; 13] = [
+    "&&", "||", "==", "!=", "<=", ">=", "!~", "<", ">", "~", "!", "(", ")",
+];
+
+/// Splits an expression into tokens
+fn tokenize(expression: &str) -> Result<Vec<Token>, FilterParseError> {
+    let mut tokens = Vec::new();
+    let mut rest = expression;
+
+    loop {
+        rest = rest.trim_start();
+        let column = expression.len() - rest.len();
+        let error = |message: String| FilterParseError { column, message };
+        let Some(c) = rest.chars().next() else {
+            break;
+        };
+
+        let kind = if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            rest = &rest[symbol.len()..];
+            TokenKind::Symbol(symbol)
+        } else if let Some(expected) = ["&&", "||", "=="].iter().find(|s| s.starts_with(c)) {
+            return Err(error(format!(
+                "unexpected '{}', did you mean '{}'?",
+                c, expected
+            )));
+        } else if c == '"' {
+            let mut text = String::new();
+            let mut chars = rest.char_indices().skip(1);
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => text.push(escaped),
+                        None => return Err(error("unterminated string".to_owned())),
+                    },
+                    Some((i, '"')) => {
+                        rest = &rest[i + 1..];
+                        break;
+                    }
+                    Some((_, c)) => text.push(c),
+                    None => return Err(error("unterminated string".to_owned())),
+                }
+            }
+            TokenKind::Quoted(text)
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "()!=<>~&|\"".contains(c))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            rest = &rest[end..];
+            TokenKind::Word(word.to_owned())
+        };
+        tokens.push(Token { kind, column });
+    }
+
+    Ok(tokens)
+}
+
+/// A recursive descent parser over the tokens of an expression
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// The length of the expression, where errors about a missing token point
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Returns the next token, or an error at the end of the expression expecting `what`
+    fn expect(&mut self, what: &str) -> Result<Token, FilterParseError> {
+        self.next().ok_or_else(|| FilterParseError {
+            column: self.end,
+            message: format!("expected {} at end of filter", what),
+        })
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found =
+            matches!(self.peek(), Some(Token { kind: TokenKind::Symbol(s), .. }) if *s == symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// `or := and ("||" and)*`
+    fn or(&mut self) -> Result<Expr, FilterParseError> {
+        let mut exprs = vec![self.and()?];
+        while self.eat("||") {
+            exprs.push(self.and()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::Or(exprs)
+        })
+    }
+
+    /// `and := unary ("&&" unary)*`
+    fn and(&mut self) -> Result<Expr, FilterParseError> {
+        let mut exprs = vec![self.unary()?];
+        while self.eat("&&") {
+            exprs.push(self.unary()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::And(exprs)
+        })
+    }
+
+    /// `unary := "!" unary | "(" or ")" | test`
+    fn unary(&mut self) -> Result<Expr, FilterParseError> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if let Some(open) = self.peek().filter(|t| t.kind == TokenKind::Symbol("(")) {
+            let open = open.clone();
+            self.pos += 1;
+            let expr = self.or()?;
+            if !self.eat(")") {
+                return Err(match self.peek() {
+                    Some(token) => token.error(format!("expected ')' before {}", token.describe())),
+                    None => open.error("unclosed '('".to_owned()),
+                });
+            }
+            return Ok(expr);
+        }
+        self.test()
+    }
+
+    /// `test := field operator value`
+    fn test(&mut self) -> Result<Expr, FilterParseError> {
+        let token = self.expect("a field")?;
+        let field = match &token.kind {
+            TokenKind::Word(word) => match word.strip_prefix('$') {
+                Some(name) if !name.is_empty() => Field::Variable(name.to_owned()),
+                _ => FIELDS
+                    .iter()
+                    .find(|(name, _)| name == word)
+                    .map(|(_, field)| field.clone())
+                    .ok_or_else(|| {
+                        let names = FIELDS.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+                        token.error(format!(
+                            "unknown field {:?}, expected one of {} or a $variable",
+                            word,
+                            names.join(", ")
+                        ))
+                    })?,
+            },
+            _ => return Err(token.error(format!("expected a field, found {}", token.describe()))),
+        };
+
+        let operator = self.expect(&format!("an operator after {}", token.describe()))?;
+        let name = match &operator.kind {
+            TokenKind::Symbol(symbol) => *symbol,
+            TokenKind::Word(word) => match word.as_str() {
+                "in" => "in",
+                "contains" => "contains",
+                "startswith" | "starts_with" => "startswith",
+                "endswith" | "ends_with" => "endswith",
+                _ => "",
+            },
+            TokenKind::Quoted(_) => "",
+        };
+        let comparison = match name {
+            "==" => Some(Comparison::Eq),
+            "!=" => Some(Comparison::Ne),
+            "<" => Some(Comparison::Lt),
+            "<=" => Some(Comparison::Le),
+            ">" => Some(Comparison::Gt),
+            ">=" => Some(Comparison::Ge),
+            _ => None,
+        };
+        if comparison.is_none()
+            && !matches!(
+                name,
+                "~" | "!~" | "in" | "contains" | "startswith" | "endswith"
+            )
+        {
+            return Err(operator.error(format!(
+                "expected an operator after {} such as ==, >=, ~ or in, found {}",
+                token.describe(),
+                operator.describe()
+            )));
+        }
+
+        let value = self.expect(&format!("a value after {}", name))?;
+        let text = match &value.kind {
+            TokenKind::Word(text) | TokenKind::Quoted(text) => text.clone(),
+            TokenKind::Symbol(_) => {
+                return Err(value.error(format!(
+                    "expected a value after {}, found {}",
+                    name,
+                    value.describe()
+                )))
+            }
+        };
+        let number = || {
+            text.parse::<f64>()
+                .ok()
+                .filter(|number| number.is_finite())
+                .ok_or_else(|| {
+                    value.error(format!(
+                        "expected a number after {}, found {:?}",
+                        name, text
+                    ))
+                })
+        };
+        let numeric = matches!(
+            field,
+            Field::Status | Field::Bytes | Field::RequestTime | Field::UpstreamTime
+        );
+
+        let condition = match (comparison, name) {
+            (Some(comparison @ (Comparison::Eq | Comparison::Ne)), _) if !numeric => {
+                Condition::Text(comparison, text)
+            }
+            (Some(comparison), _) => Condition::Number(comparison, number()?),
+            (None, "~" | "!~") => {
+                let regex = Regex::new(&text).map_err(|e| {
+                    // Syntax errors repeat the regex with a caret, keep only their message
+                    let message = e.to_string();
+                    let message = message.lines().last().unwrap_or_default();
+                    let message = message.trim_start_matches("error: ");
+                    value.error(format!("invalid regex: {}", message))
+                })?;
+                if name == "~" {
+                    Condition::Matches(regex)
+                } else {
+                    Condition::NotMatches(regex)
+                }
+            }
+            (None, "in") => Condition::In(Network::parse(&text).ok_or_else(|| {
+                value.error(format!(
+                    "expected an IP network such as 10.0.0.0/8 after in, found {:?}",
+                    text
+                ))
+            })?),
+            (None, "contains") => Condition::Contains(text),
+            (None, "startswith") => Condition::StartsWith(text),
+            _ => Condition::EndsWith(text),
+        };
+
+        Ok(Expr::Test(field, condition))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn line() -> NginxLogLine {
+        let mut line = crate::combined::parse_line(concat!(
+            r#"10.1.2.3 - alice [10/Oct/2026:13:55:36 +0000] "GET /api/us%65rs?id=1 HTTP/1.1" "#,
+            r#"502 512 "https://example.com/" "curl/8.0 \"quoted\"""#
+        ))
+        .unwrap();
+        line.request_time = Some(Duration::from_millis(1500));
+        line.extra
+            .insert("host".to_owned(), "example.com".to_owned());
+        line.extra.insert("dir".to_owned(), r"C:\logs".to_owned());
+        line
+    }
+
+    fn matches(filter: &str, line: &NginxLogLine) -> bool {
+        filter.parse::<Filter>().unwrap().matches(line)
+    }
+
+    fn error(filter: &str) -> (usize, String) {
+        let error = filter.parse::<Filter>().unwrap_err();
+        (error.column, error.message)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let line = line();
+        assert!(matches(
+            "status == 200 && bytes == 0 || status == 502",
+            &line
+        ));
+        assert!(matches(
+            "status == 502 || status == 200 && bytes == 0",
+            &line
+        ));
+        assert!(!matches(
+            "(status == 502 || status == 200) && bytes == 0",
+            &line
+        ));
+        assert!(!matches(
+            "status == 502 && (bytes == 0 || status == 200)",
+            &line
+        ));
+        assert!(matches("((status == 502))", &line));
+    }
+
+    #[test]
+    fn not_binds_tightest() {
+        let line = line();
+        assert!(matches("!status == 200", &line));
+        assert!(!matches("!(status == 502)", &line));
+        assert!(matches("!!(status == 502)", &line));
+        assert!(matches("!status == 502 || bytes == 512", &line));
+        assert!(!matches("!(status == 502 || bytes == 512)", &line));
+    }
+
+    #[test]
+    fn tests_each_field() {
+        let line = line();
+        for filter in [
+            "status != 200",
+            "status == 502.0",
+            "bytes > 511 && bytes <= 512",
+            "request_time >= 1.5 && request_time < 1.6",
+            "ip == 10.1.2.3",
+            "user == alice",
+            "method == GET",
+            "path == /api/users",
+            "path ~ ^/api/",
+            r#"request contains "?id=1""#,
+            "request startswith GET",
+            "referrer startswith https:",
+            "referer endswith /",
+            r#"agent == "curl/8.0 \"quoted\"""#,
+            r#"$dir == "C:\\logs""#,
+            "$host == example.com",
+            "path !~ ^/web/",
+        ] {
+            assert!(matches(filter, &line), "{}", filter);
+        }
+        for filter in [
+            "status < 500",
+            "path ~ ^/web/",
+            "path !~ ^/api/",
+            "agent ~ bot",
+            "$host != example.com",
+        ] {
+            assert!(!matches(filter, &line), "{}", filter);
+        }
+    }
+
+    #[test]
+    fn tests_on_missing_fields_are_false() {
+        let line = line();
+        for filter in [
+            "upstream_time >= 0",
+            "upstream_response_time < 0",
+            "$missing == x",
+            "$missing != x",
+            "$missing ~ .",
+            "$missing !~ .",
+        ] {
+            assert!(!matches(filter, &line), "{}", filter);
+            assert!(matches(&format!("!({})", filter), &line), "{}", filter);
+        }
+
+        let malformed = NginxLogLine {
+            request: "\\x16\\x03\\x01".to_owned(),
+            ..line
+        };
+        assert!(!matches("method == GET", &malformed));
+        assert!(!matches("method != GET", &malformed));
+    }
+
+    #[test]
+    fn matches_networks() {
+        let v4 = line();
+        let v6 = NginxLogLine {
+            remote_ip: "2001:db8::1".to_owned(),
+            ..line()
+        };
+
+        for (network, in_v4, in_v6) in [
+            ("10.0.0.0/8", true, false),
+            ("10.1.2.3", true, false),
+            ("10.1.2.3/32", true, false),
+            ("10.1.2.4", false, false),
+            ("10.1.2.0/31", false, false),
+            ("0.0.0.0/0", true, false),
+            ("2001:db8::/32", false, true),
+            ("2001:db9::/32", false, false),
+            ("2001:db8::1", false, true),
+            ("2001:db8::/128", false, false),
+            ("::/0", false, true),
+        ] {
+            let filter = format!("ip in {}", network);
+            assert_eq!(matches(&filter, &v4), in_v4, "{}", filter);
+            assert_eq!(matches(&filter, &v6), in_v6, "{}", filter);
+        }
+
+        assert_eq!(
+            error("ip in 10.0.0.0/33"),
+            (
+                6,
+                r#"expected an IP network such as 10.0.0.0/8 after in, found "10.0.0.0/33""#
+                    .to_owned()
+            )
+        );
+        assert_eq!(error("ip in ::/129").0, 6);
+        assert_eq!(error("ip in example.com").0, 6);
+    }
+
+    #[test]
+    fn reports_the_column_of_errors() {
+        for (filter, column, message) in [
+            (
+                "status >= 500 && path ~",
+                23,
+                "expected a value after ~ at end of filter",
+            ),
+            (
+                "status >= 500 && ip in 10.0.0.0/33",
+                23,
+                r#"expected an IP network such as 10.0.0.0/8 after in, found "10.0.0.0/33""#,
+            ),
+            ("status = 500", 7, "unexpected '=', did you mean '=='?"),
+            (
+                "status >= 500 & bytes > 0",
+                14,
+                "unexpected '&', did you mean '&&'?",
+            ),
+            (
+                "status >= abc",
+                10,
+                r#"expected a number after >=, found "abc""#,
+            ),
+            ("path == \"/api", 8, "unterminated string"),
+            ("(status == 1", 0, "unclosed '('"),
+            ("status == 1)", 11, "unmatched ')'"),
+            ("(status == 1 bytes", 13, r#"expected ')' before "bytes""#),
+            (
+                "status == 1 bytes == 2",
+                12,
+                r#"expected && or || before "bytes""#,
+            ),
+            ("", 0, "expected a field at end of filter"),
+            (
+                "status",
+                6,
+                r#"expected an operator after "status" at end of filter"#,
+            ),
+            (
+                "status is 1",
+                7,
+                r#"expected an operator after "status" such as ==, >=, ~ or in, found "is""#,
+            ),
+            ("== 1", 0, "expected a field, found '=='"),
+            ("status == )", 10, "expected a value after ==, found ')'"),
+        ] {
+            assert_eq!(error(filter), (column, message.to_owned()), "{}", filter);
+        }
+
+        let (column, message) = error("stat == 1");
+        assert_eq!(column, 0);
+        assert!(message.starts_with(r#"unknown field "stat", expected one of status, bytes"#));
+        assert_eq!(error("$ == 1").0, 0);
+    }
+
+    #[test]
+    fn reports_invalid_regexes() {
+        assert_eq!(
+            error(r#"status >= 500 && agent ~ "bot(""#),
+            (25, "invalid regex: unclosed group".to_owned())
+        );
+        assert_eq!(error("path !~ [a-").0, 8);
+        assert!(!error("path !~ [a-").1.contains('\n'));
+    }
+}
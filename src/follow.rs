@@ -0,0 +1,281 @@
+/// Reading of log files that are still being written to, like `tail -F`
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// The number of bytes read from the file at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Identifies the file a path refers to, so that rotation can be detected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileId {
+    #[cfg(unix)]
+    dev: u64,
+    #[cfg(unix)]
+    ino: u64,
+}
+
+impl FileId {
+    #[cfg(unix)]
+    fn of(metadata: &std::fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        Self {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn of(_metadata: &std::fs::Metadata) -> Self {
+        Self {}
+    }
+}
+
+/// A reader over a log file that keeps returning lines as they are appended
+///
+/// Reaching the end of the file is reported as a normal end of input, but reading again later
+/// returns any lines appended since. Only complete lines are returned, so a line that is still
+/// being written is held back until its newline arrives. Wrap it in a `BufReader` and an
+/// [`NginxLogReader`](crate::nginx_log::NginxLogReader), and call `next` again after it returns
+/// `None` to pick up new lines.
+///
+/// The file is read a chunk at a time, so memory use is bounded by the chunk size and the longest
+/// line rather than by the size of the log.
+///
+/// Rotation is handled in both of logrotate's styles. When the path is renamed and a new file
+/// created in its place, the old file is read to the end before switching to the new one. When
+/// the file is copied and truncated, reading restarts from its beginning.
+pub struct LogFollower {
+    path: PathBuf,
+    file: File,
+    id: FileId,
+    /// Bytes read from the current file
+    position: u64,
+    /// Bytes read but not yet returned, complete lines up to `complete` and then a partial line
+    buf: Vec<u8>,
+    /// The start of the bytes in `buf` not yet returned
+    start: usize,
+    /// The end of the last complete line in `buf`
+    complete: usize,
+}
+
+impl LogFollower {
+    /// Opens a file to follow, starting from its beginning
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the log file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened
+    pub fn open<P>(path: P) -> std::io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_owned();
+        let file = File::open(&path)?;
+        let id = FileId::of(&file.metadata()?);
+
+        Ok(Self {
+            path,
+            file,
+            id,
+            position: 0,
+            buf: Vec::new(),
+            start: 0,
+            complete: 0,
+        })
+    }
+
+    /// Reads chunks of the file until `buf` holds a complete line or the end is reached
+    fn fill(&mut self) -> std::io::Result<()> {
+        self.buf.drain(..self.complete);
+        self.start = 0;
+        self.complete = 0;
+
+        loop {
+            let searched = self.buf.len();
+            if self.read_chunk()? > 0 {
+                let newline = self.buf[searched..].iter().rposition(|&b| b == b'\n');
+                if let Some(newline) = newline {
+                    self.complete = searched + newline + 1;
+                    return Ok(());
+                }
+            } else if !self.check_rotation()? || self.complete > 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Appends up to a chunk of the file to `buf`, returning the number of bytes read
+    fn read_chunk(&mut self) -> std::io::Result<usize> {
+        let len = self.buf.len();
+        self.buf.resize(len + CHUNK_SIZE, 0);
+        let read = match self.file.read(&mut self.buf[len..]) {
+            Ok(read) => read,
+            Err(e) => {
+                self.buf.truncate(len);
+                return Err(e);
+            }
+        };
+        self.buf.truncate(len + read);
+        self.position += read as u64;
+        Ok(read)
+    }
+
+    /// Switches to a new file or restarts from the beginning if the log was rotated
+    ///
+    /// Returns whether it was, so that reading continues from the start of a file.
+    fn check_rotation(&mut self) -> std::io::Result<bool> {
+        // The path can briefly not exist between a rename and nginx reopening its logs
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            return Ok(false);
+        };
+
+        if FileId::of(&metadata) != self.id {
+            // The old file has been read to the end, keep a final line even without a newline
+            if !self.buf.is_empty() {
+                self.buf.push(b'\n');
+                self.complete = self.buf.len();
+            }
+            self.file = File::open(&self.path)?;
+            self.id = FileId::of(&self.file.metadata()?);
+        } else if metadata.len() < self.position {
+            self.buf.clear();
+            self.file.seek(SeekFrom::Start(0))?;
+        } else {
+            return Ok(false);
+        }
+
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl Read for LogFollower {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.start >= self.complete {
+            self.fill()?;
+        }
+
+        let ready = &self.buf[self.start..self.complete];
+        let len = ready.len().min(buf.len());
+        buf[..len].copy_from_slice(&ready[..len]);
+        self.start += len;
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    /// A directory for a test's log files, removed when the test ends
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "nginx-parser-follow-{}-{}",
+                name,
+                std::process::id()
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    /// Reads until the follower reports the end of its input, like one poll of `--follow`
+    fn poll(follower: &mut LogFollower) -> String {
+        let mut read = String::new();
+        follower.read_to_string(&mut read).unwrap();
+        read
+    }
+
+    #[test]
+    fn returns_appended_lines_once_complete() {
+        let dir = TempDir::new("append");
+        let log = dir.0.join("access.log");
+        append(&log, "1\n2");
+
+        let mut follower = LogFollower::open(&log).unwrap();
+        assert_eq!(poll(&mut follower), "1\n");
+        assert_eq!(poll(&mut follower), "");
+        append(&log, "2\n3\n");
+        assert_eq!(poll(&mut follower), "22\n3\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_renamed_logs() {
+        let dir = TempDir::new("rename");
+        let log = dir.0.join("access.log");
+        append(&log, "1\n");
+
+        let mut follower = LogFollower::open(&log).unwrap();
+        assert_eq!(poll(&mut follower), "1\n");
+        append(&log, "2\n3");
+        std::fs::rename(&log, dir.0.join("access.log.1")).unwrap();
+        assert_eq!(poll(&mut follower), "2\n");
+        assert_eq!(poll(&mut follower), "");
+
+        append(&log, "4\n");
+        assert_eq!(poll(&mut follower), "3\n4\n");
+        append(&log, "5\n");
+        assert_eq!(poll(&mut follower), "5\n");
+    }
+
+    #[test]
+    fn reads_large_logs_a_chunk_at_a_time() {
+        let dir = TempDir::new("chunks");
+        let log = dir.0.join("access.log");
+        let lines = (0..100_000)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        append(&log, &lines);
+
+        let mut follower = LogFollower::open(&log).unwrap();
+        assert_eq!(poll(&mut follower), lines);
+        assert!(follower.buf.capacity() <= 2 * (CHUNK_SIZE + 16));
+
+        let long = format!("{}\n", "x".repeat(3 * CHUNK_SIZE));
+        append(&log, &long[..CHUNK_SIZE]);
+        assert_eq!(poll(&mut follower), "");
+        append(&log, &long[CHUNK_SIZE..]);
+        append(&log, "short\n");
+        assert_eq!(poll(&mut follower), long + "short\n");
+    }
+
+    #[test]
+    fn restarts_truncated_logs() {
+        let dir = TempDir::new("truncate");
+        let log = dir.0.join("access.log");
+        append(&log, "1\n2\n");
+
+        let mut follower = LogFollower::open(&log).unwrap();
+        assert_eq!(poll(&mut follower), "1\n2\n");
+        std::fs::write(&log, "").unwrap();
+        assert_eq!(poll(&mut follower), "");
+        append(&log, "3\n");
+        assert_eq!(poll(&mut follower), "3\n");
+    }
+}
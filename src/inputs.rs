@@ -0,0 +1,192 @@
+/// Resolution of command line inputs into an ordered list of log files
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+/// The input name that refers to standard input
+pub const STDIN: &str = "-";
+
+/// Expands paths, glob patterns and directories into the log files they refer to
+///
+/// Patterns containing `*`, `?` or `[` are expanded without relying on the shell, and
+/// directories are replaced with the files directly inside them. The resulting files are
+/// deduplicated and sorted with [`rotation_order`], so rotated logs are read oldest first.
+/// [`STDIN`] is passed through unchanged and always comes first.
+///
+/// # Arguments
+///
+/// * `inputs` - The paths, patterns and directories to expand
+///
+/// # Errors
+///
+/// Returns an error if a pattern is invalid or matches nothing, or if a directory cannot be read
+pub fn expand<P>(inputs: &[P]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>>
+where
+    P: AsRef<Path>,
+{
+    let mut files = Vec::new();
+    let mut stdin = false;
+
+    for input in inputs {
+        let input = input.as_ref();
+        let pattern = input.to_string_lossy();
+
+        if input == Path::new(STDIN) {
+            stdin = true;
+        } else if pattern.contains(['*', '?', '[']) && !input.exists() {
+            let before = files.len();
+            for path in glob::glob(&pattern)? {
+                let path = path?;
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+            if files.len() == before {
+                return Err(format!("no files match {}", pattern).into());
+            }
+        } else if input.is_dir() {
+            for entry in std::fs::read_dir(input)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+        } else {
+            files.push(input.to_owned());
+        }
+    }
+
+    files.sort_by(|a, b| rotation_order(a, b));
+    files.dedup();
+    if stdin {
+        files.insert(0, PathBuf::from(STDIN));
+    }
+
+    Ok(files)
+}
+
+/// Where a file falls in a logrotate sequence
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Rotation {
+    /// `access.log.3.gz`, where higher numbers are older
+    Numbered(std::cmp::Reverse<u64>),
+    /// `access.log-20261016.gz`, as written with logrotate's `dateext`
+    Dated(u64),
+    /// `access.log`, the file nginx is currently writing to
+    Current,
+}
+
+/// Splits a file name into the name of the log it was rotated from and its place in the rotation
+fn rotation(path: &Path) -> (String, Rotation) {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut base = name.as_str();
+    for extension in [".gz", ".bz2", ".zst"] {
+        base = base.strip_suffix(extension).unwrap_or(base);
+    }
+
+    if let Some((log, number)) = base.rsplit_once('.') {
+        if let Ok(number) = number.parse() {
+            return (
+                log.to_owned(),
+                Rotation::Numbered(std::cmp::Reverse(number)),
+            );
+        }
+    }
+    if let Some((log, date)) = base.rsplit_once('-') {
+        if date.len() >= 8 && date.bytes().all(|b| b.is_ascii_digit()) {
+            if let Ok(date) = date.parse() {
+                return (log.to_owned(), Rotation::Dated(date));
+            }
+        }
+    }
+
+    (base.to_owned(), Rotation::Current)
+}
+
+/// Orders log files so that rotated files come before the files they were rotated from
+///
+/// Files are grouped by directory and the name of the log they were rotated from. Within a
+/// group, numbered rotations (`access.log.2.gz`) come first from highest to lowest, then dated
+/// rotations (`access.log-20261016`) from oldest to newest, then the current file.
+pub fn rotation_order(a: &Path, b: &Path) -> Ordering {
+    let (a_log, a_rotation) = rotation(a);
+    let (b_log, b_rotation) = rotation(b);
+
+    a.parent()
+        .cmp(&b.parent())
+        .then_with(|| a_log.cmp(&b_log))
+        .then_with(|| a_rotation.cmp(&b_rotation))
+        .then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_rotated_files_oldest_first() {
+        let mut files = [
+            "access.log",
+            "other/access.log",
+            "access.log-20261016",
+            "error.log",
+            "access.log.1",
+            "access.log-20261015.gz",
+            "error.log.1",
+            "access.log.2.gz",
+            "access.log.10.gz",
+        ]
+        .map(PathBuf::from);
+        files.sort_by(|a, b| rotation_order(a, b));
+
+        assert_eq!(
+            files.map(|file| file.to_string_lossy().into_owned()),
+            [
+                "access.log.10.gz",
+                "access.log.2.gz",
+                "access.log.1",
+                "access.log-20261015.gz",
+                "access.log-20261016",
+                "access.log",
+                "error.log.1",
+                "error.log",
+                "other/access.log",
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_directories_and_patterns() {
+        let dir = std::env::temp_dir().join(format!("nginx-parser-inputs-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("logs")).unwrap();
+        for name in ["access.log", "access.log.1", "access.log.2.gz", "error.log"] {
+            std::fs::write(dir.join("logs").join(name), "").unwrap();
+        }
+
+        let logs = dir.join("logs");
+        let pattern = logs.join("access.log*");
+        let files = expand(&[pattern.as_path(), Path::new(STDIN), logs.as_path()]).unwrap();
+        let names = files
+            .iter()
+            .map(|file| file.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "-",
+                "access.log.2.gz",
+                "access.log.1",
+                "access.log",
+                "error.log"
+            ]
+        );
+
+        let error = expand(&[logs.join("missing*")]).unwrap_err();
+        assert!(error.to_string().starts_with("no files match"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,21 @@
+pub mod combined;
+pub mod compression;
+pub mod csv;
+pub mod duration;
+pub mod endpoints;
+pub mod filter;
+pub mod follow;
+pub mod inputs;
+pub mod log_format;
+pub mod nginx_log;
+pub mod normalize;
+pub mod output;
+pub mod parallel;
+pub mod request;
+pub mod series;
+pub mod sketch;
+pub mod stats;
+mod table;
+pub mod time_range;
+pub mod timestamp;
+pub mod top;
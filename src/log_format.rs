@@ -0,0 +1,591 @@
+/// Compiles nginx `log_format` directives into line parsers
+use chrono::{DateTime, FixedOffset};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use crate::combined::unescape;
+use crate::duration;
+use crate::nginx_log::NginxLogLine;
+use crate::timestamp;
+
+/// The format string nginx uses for its predefined `combined` format
+pub const COMBINED: &str = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent""#;
+
+/// Error returned when a format string cannot be compiled or a line does not match it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFormatError {
+    /// Byte offset into the format string or line where the error was found
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for LogFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at column {}", self.message, self.column + 1)
+    }
+}
+
+impl std::error::Error for LogFormatError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Variable(String),
+}
+
+/// A parser for log lines written with a specific nginx `log_format`
+///
+/// Variables that correspond to a field of [`NginxLogLine`] are parsed into that field, every
+/// other variable is stored in [`NginxLogLine::extra`] under its name without the leading `$`.
+///
+/// | Variable                                | Field                    |
+/// |-----------------------------------------|--------------------------|
+/// | `$time_local`, `$time_iso8601`, `$msec` | `time`                   |
+/// | `$remote_addr`                          | `remote_ip`              |
+/// | `$remote_user`                          | `remote_user`            |
+/// | `$request`                              | `request`                |
+/// | `$status`                               | `response`               |
+/// | `$body_bytes_sent`, `$bytes_sent`       | `bytes`                  |
+/// | `$http_referer`                         | `referrer`               |
+/// | `$http_user_agent`                      | `agent`                  |
+/// | `$request_time`                         | `request_time`           |
+/// | `$upstream_response_time`               | `upstream_response_time` |
+///
+/// Fields without a matching variable in the format are left as `-`, `0` for numbers, the Unix
+/// epoch for `time`, or `None` for timings. Escape sequences in text values are decoded with
+/// [`unescape`], as in [`crate::combined::parse_line`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFormatParser {
+    segments: Vec<Segment>,
+}
+
+impl LogFormatParser {
+    /// Compiles a `log_format` string such as [`COMBINED`]
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format string, with the quotes from the nginx config already removed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the format contains an empty variable name, or two variables with no
+    /// text between them, since the boundary between their values would be ambiguous
+    pub fn new(format: &str) -> Result<Self, LogFormatError> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            if c != '$' {
+                literal.push(c);
+                continue;
+            }
+
+            let braced = chars.next_if(|&(_, c)| c == '{').is_some();
+            let mut name = String::new();
+            while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '_')
+            {
+                name.push(c);
+            }
+            if braced && chars.next_if(|&(_, c)| c == '}').is_none() {
+                return Err(LogFormatError {
+                    column: i,
+                    message: "unterminated ${ in variable".to_owned(),
+                });
+            }
+            if name.is_empty() {
+                return Err(LogFormatError {
+                    column: i,
+                    message: "missing variable name after $".to_owned(),
+                });
+            }
+
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            } else if let Some(Segment::Variable(previous)) = segments.last() {
+                return Err(LogFormatError {
+                    column: i,
+                    message: format!(
+                        "variables ${} and ${} must be separated by text",
+                        previous, name
+                    ),
+                });
+            }
+            segments.push(Segment::Variable(name));
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Compiles the `log_format` with the given name from an nginx config file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the nginx config file
+    /// * `name` - The name of the format, as used in `access_log` directives
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, or see [`LogFormatParser::from_config_str`]
+    pub fn from_config<P>(path: P, name: &str) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let config = std::fs::read_to_string(path)?;
+        Ok(Self::from_config_str(&config, name)?)
+    }
+
+    /// Compiles the `log_format` with the given name from the contents of an nginx config file
+    ///
+    /// Only `log_format` directives in the given text are considered, `include` directives are
+    /// not followed. The predefined `combined` format is used if the config does not redefine it.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The contents of an nginx config file
+    /// * `name` - The name of the format, as used in `access_log` directives
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no format with that name is defined, or if it cannot be compiled
+    pub fn from_config_str(config: &str, name: &str) -> Result<Self, LogFormatError> {
+        let mut directive: Vec<String> = Vec::new();
+
+        for token in config_tokens(config)? {
+            match token {
+                ConfigToken::End => {
+                    if let [keyword, format_name, rest @ ..] = directive.as_slice() {
+                        if keyword == "log_format" && format_name == name {
+                            let rest = match rest {
+                                [escape, rest @ ..] if escape.starts_with("escape=") => rest,
+                                _ => rest,
+                            };
+                            let format = rest.concat();
+                            return Self::new(&format);
+                        }
+                    }
+                    directive.clear();
+                }
+                ConfigToken::Word(word) => directive.push(word),
+            }
+        }
+
+        if name == "combined" {
+            return Self::new(COMBINED);
+        }
+
+        Err(LogFormatError {
+            column: 0,
+            message: format!("log_format {:?} not found in config", name),
+        })
+    }
+
+    /// Returns the names of all variables in the format, in order and without the leading `$`
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Variable(name) => Some(name.as_str()),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    /// Splits a line into the values of each variable in the format
+    ///
+    /// Each value extends up to the next occurrence of the text that follows the variable in the
+    /// format, skipping occurrences escaped with a backslash.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line does not match the format
+    pub fn captures<'a>(&self, line: &'a str) -> Result<Vec<(&str, &'a str)>, LogFormatError> {
+        let mut captures = Vec::new();
+        let mut pos = 0;
+        for capture in self.captures_iter(line) {
+            let (name, value) = capture?;
+            pos = value.as_ptr() as usize - line.as_ptr() as usize + value.len();
+            captures.push((name, value));
+        }
+        if let Some(Segment::Literal(text)) = self.segments.last() {
+            pos += text.len();
+        }
+
+        if pos != line.len() {
+            return Err(LogFormatError {
+                column: pos,
+                message: "unexpected text at end of line".to_owned(),
+            });
+        }
+
+        Ok(captures)
+    }
+
+    /// Splits a line into the values of each variable in the format as they are found, see
+    /// [`LogFormatParser::captures`]
+    ///
+    /// Stops after the first error, and does not check that the line ends after the format.
+    fn captures_iter<'s, 'a>(
+        &'s self,
+        line: &'a str,
+    ) -> impl Iterator<Item = Result<(&'s str, &'a str), LogFormatError>> {
+        let mut segments = self.segments.iter().enumerate();
+        let mut pos = 0;
+        let mut failed = false;
+
+        std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            for (i, segment) in segments.by_ref() {
+                match segment {
+                    Segment::Literal(text) => {
+                        if !line[pos..].starts_with(text.as_str()) {
+                            failed = true;
+                            return Some(Err(LogFormatError {
+                                column: pos,
+                                message: format!("expected {:?}", text),
+                            }));
+                        }
+                        pos += text.len();
+                    }
+                    Segment::Variable(name) => {
+                        let end = match self.segments.get(i + 1) {
+                            Some(Segment::Literal(text)) => {
+                                match find_unescaped(&line[pos..], text) {
+                                    Some(end) => pos + end,
+                                    None => {
+                                        failed = true;
+                                        return Some(Err(LogFormatError {
+                                            column: pos,
+                                            message: format!("expected {:?} after ${}", text, name),
+                                        }));
+                                    }
+                                }
+                            }
+                            _ => line.len(),
+                        };
+                        let value = &line[pos..end];
+                        pos = end;
+                        return Some(Ok((name.as_str(), value)));
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// Parses only the time of a line written with this format
+    ///
+    /// The line is only split up to the time variable, so this is much cheaper than
+    /// [`LogFormatParser::parse_line`].
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - A single log line without its trailing newline
+    ///
+    /// # Returns
+    ///
+    /// The time of the line, or `None` if the format has no time variable or the line does not
+    /// match the format up to it
+    pub fn parse_time(&self, line: &str) -> Option<DateTime<FixedOffset>> {
+        for capture in self.captures_iter(line) {
+            let (name, value) = capture.ok()?;
+            if matches!(name, "time_local" | "time_iso8601" | "msec") {
+                return timestamp::parse(value).ok();
+            }
+        }
+        None
+    }
+
+    /// Parses a line written with this format
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - A single log line without its trailing newline
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line does not match the format, if `$status` or the bytes variable
+    /// is not a number, or if the time or a timing cannot be parsed
+    pub fn parse_line(&self, line: &str) -> Result<NginxLogLine, LogFormatError> {
+        let mut log_line = NginxLogLine {
+            time: DateTime::UNIX_EPOCH.fixed_offset(),
+            remote_ip: "-".to_owned(),
+            remote_user: "-".to_owned(),
+            request: "-".to_owned(),
+            response: 0,
+            bytes: 0,
+            referrer: "-".to_owned(),
+            agent: "-".to_owned(),
+            request_time: None,
+            upstream_response_time: None,
+            extra: BTreeMap::new(),
+        };
+        let mut has_body_bytes = false;
+
+        for (name, value) in self.captures(line)? {
+            let column = value.as_ptr() as usize - line.as_ptr() as usize;
+            let number_error = || LogFormatError {
+                column,
+                message: format!("invalid ${} {:?}", name, value),
+            };
+
+            match name {
+                "time_local" | "time_iso8601" | "msec" => {
+                    log_line.time = timestamp::parse(value).map_err(|e| LogFormatError {
+                        column,
+                        message: e.to_string(),
+                    })?;
+                }
+                "remote_addr" => log_line.remote_ip = unescape(value).into_owned(),
+                "remote_user" => log_line.remote_user = unescape(value).into_owned(),
+                "request" => log_line.request = unescape(value).into_owned(),
+                "status" => log_line.response = value.parse().map_err(|_| number_error())?,
+                "body_bytes_sent" => {
+                    log_line.bytes = value.parse().map_err(|_| number_error())?;
+                    has_body_bytes = true;
+                }
+                "bytes_sent" if !has_body_bytes => {
+                    log_line.bytes = value.parse().map_err(|_| number_error())?;
+                    log_line.extra.insert(name.to_owned(), value.to_owned());
+                }
+                "http_referer" => log_line.referrer = unescape(value).into_owned(),
+                "http_user_agent" => log_line.agent = unescape(value).into_owned(),
+                "request_time" | "upstream_response_time" => {
+                    let duration = duration::parse(value).map_err(|e| LogFormatError {
+                        column,
+                        message: e.to_string(),
+                    })?;
+                    if name == "request_time" {
+                        log_line.request_time = duration;
+                    } else {
+                        log_line.upstream_response_time = duration;
+                    }
+                }
+                _ => {
+                    let value = unescape(value).into_owned();
+                    log_line.extra.insert(name.to_owned(), value);
+                }
+            }
+        }
+
+        Ok(log_line)
+    }
+}
+
+/// Finds the first occurrence of `needle` in `haystack` not preceded by a backslash escape
+fn find_unescaped(haystack: &str, needle: &str) -> Option<usize> {
+    let mut start = 0;
+    while let Some(found) = haystack[start..].find(needle) {
+        let index = start + found;
+        let backslashes = haystack[..index]
+            .bytes()
+            .rev()
+            .take_while(|&b| b == b'\\')
+            .count();
+        if backslashes % 2 == 0 {
+            return Some(index);
+        }
+        start = index + needle.len().max(1);
+    }
+    None
+}
+
+enum ConfigToken {
+    Word(String),
+    /// A `;`, `{` or `}` ending the current directive
+    End,
+}
+
+/// Splits nginx config text into words, removing comments and quotes
+fn config_tokens(config: &str) -> Result<Vec<ConfigToken>, LogFormatError> {
+    let mut tokens = Vec::new();
+    let mut chars = config.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '#' => while chars.next_if(|&(_, c)| c != '\n').is_some() {},
+            ';' | '{' | '}' => tokens.push(ConfigToken::End),
+            '"' | '\'' => {
+                let unterminated = LogFormatError {
+                    column: i,
+                    message: "unterminated quote in config".to_owned(),
+                };
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped @ ('"' | '\'' | '\\'))) => word.push(escaped),
+                            Some((_, 'n')) => word.push('\n'),
+                            Some((_, 't')) => word.push('\t'),
+                            Some((_, other)) => {
+                                word.push('\\');
+                                word.push(other);
+                            }
+                            None => return Err(unterminated),
+                        },
+                        Some((_, quote)) if quote == c => {
+                            tokens.push(ConfigToken::Word(word));
+                            break;
+                        }
+                        Some((_, other)) => word.push(other),
+                        None => return Err(unterminated),
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = String::from(c);
+                while let Some((_, c)) = chars
+                    .next_if(|&(_, c)| !c.is_whitespace() && !matches!(c, ';' | '{' | '}' | '#'))
+                {
+                    word.push(c);
+                }
+                tokens.push(ConfigToken::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combined_format_parses_like_the_combined_parser() {
+        let parser = LogFormatParser::new(COMBINED).unwrap();
+        for line in [
+            concat!(
+                r#"127.0.0.1 - frank [10/Oct/2026:13:55:36 +0200] "GET /a.gif HTTP/1.0" 200 2326 "#,
+                r#""http://www.example.com/start.html" "Mozilla/4.08 [en] (Win98; I ;Nav)""#
+            ),
+            concat!(
+                r#"10.0.0.2 - - [10/Oct/2026:13:55:37 +0000] "GET /caf\xC3\xA9 HTTP/1.1" 500 120 "#,
+                r#""-" "curl/8.0 \x22quoted\x22 \"json\"""#
+            ),
+            r#"10.0.0.3 - - [10/Oct/2026:13:55:38 +0000] "\x16\x03\x01" 400 157 "-" "-""#,
+        ] {
+            assert_eq!(
+                parser.parse_line(line).unwrap(),
+                crate::combined::parse_line(line).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn compiles_variables() {
+        let parser = LogFormatParser::new("${remote_addr}:${status}|$request_time $host").unwrap();
+        let variables = parser.variables().collect::<Vec<_>>();
+        assert_eq!(variables, ["remote_addr", "status", "request_time", "host"]);
+
+        let line = parser.parse_line("10.0.0.1:404|0.250 example.com").unwrap();
+        assert_eq!(line.remote_ip, "10.0.0.1");
+        assert_eq!(line.response, 404);
+        assert_eq!(line.request_time.unwrap().as_millis(), 250);
+        assert_eq!(line.extra["host"], "example.com");
+
+        let error = LogFormatParser::new("$status$body_bytes_sent").unwrap_err();
+        assert_eq!(error.column, 7);
+        assert_eq!(
+            error.message,
+            "variables $status and $body_bytes_sent must be separated by text"
+        );
+        assert_eq!(LogFormatParser::new("a ${status").unwrap_err().column, 2);
+        assert_eq!(LogFormatParser::new("a $ b").unwrap_err().column, 2);
+    }
+
+    #[test]
+    fn checks_text_around_variables() {
+        let parser = LogFormatParser::new(r#"[$status] "$request" end"#).unwrap();
+        let line = parser
+            .parse_line(r#"[200] "GET /\"a\" HTTP/1.1" end"#)
+            .unwrap();
+        assert_eq!(line.request, r#"GET /"a" HTTP/1.1"#);
+
+        let error = parser
+            .parse_line(r#"[200] "GET / HTTP/1.1" end!"#)
+            .unwrap_err();
+        assert_eq!(
+            (error.column, error.message.as_str()),
+            (26, "unexpected text at end of line")
+        );
+        let error = parser
+            .parse_line(r#"[200] "GET / HTTP/1.1" en"#)
+            .unwrap_err();
+        assert_eq!(
+            (error.column, error.message.as_str()),
+            (7, r#"expected "\" end" after $request"#)
+        );
+        let error = parser
+            .parse_line(r#"200] "GET / HTTP/1.1" end"#)
+            .unwrap_err();
+        assert_eq!(
+            (error.column, error.message.as_str()),
+            (0, r#"expected "[""#)
+        );
+        let error = parser
+            .parse_line(r#"[ok] "GET / HTTP/1.1" end"#)
+            .unwrap_err();
+        assert_eq!(
+            (error.column, error.message.as_str()),
+            (1, r#"invalid $status "ok""#)
+        );
+    }
+
+    #[test]
+    fn prefers_body_bytes_sent() {
+        for format in [
+            "$bytes_sent $body_bytes_sent",
+            "$body_bytes_sent $bytes_sent",
+        ] {
+            let parser = LogFormatParser::new(format).unwrap();
+            let line = parser.parse_line(if format.starts_with("$bytes") {
+                "300 100"
+            } else {
+                "100 300"
+            });
+            assert_eq!(line.unwrap().bytes, 100);
+        }
+
+        let parser = LogFormatParser::new("$bytes_sent").unwrap();
+        let line = parser.parse_line("300").unwrap();
+        assert_eq!(line.bytes, 300);
+        assert_eq!(line.extra["bytes_sent"], "300");
+    }
+
+    #[test]
+    fn finds_formats_in_configs() {
+        let config = r#"
+            http {
+                # log_format main '$status';
+                log_format  main  escape=json '$remote_addr - [$time_local] '
+                                  "\"$request\" $status" # the request
+                                  ' $body_bytes_sent';
+                log_format other '$msec';
+                access_log /var/log/nginx/access.log main;
+            }
+        "#;
+
+        let parser = LogFormatParser::from_config_str(config, "main").unwrap();
+        assert_eq!(
+            parser,
+            LogFormatParser::new(
+                r#"$remote_addr - [$time_local] "$request" $status $body_bytes_sent"#
+            )
+            .unwrap()
+        );
+        let parser = LogFormatParser::from_config_str(config, "other").unwrap();
+        assert_eq!(parser.variables().collect::<Vec<_>>(), ["msec"]);
+        let parser = LogFormatParser::from_config_str(config, "combined").unwrap();
+        assert_eq!(parser, LogFormatParser::new(COMBINED).unwrap());
+
+        let error = LogFormatParser::from_config_str(config, "missing").unwrap_err();
+        assert_eq!(error.message, r#"log_format "missing" not found in config"#);
+        let error = LogFormatParser::from_config_str("log_format main '$status;", "main");
+        assert_eq!(error.unwrap_err().column, 16);
+    }
+}
@@ -1,26 +1,770 @@
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use nginx_parser::endpoints::EndpointColumn;
+use nginx_parser::filter::{Filter, FilterParseError};
+use nginx_parser::log_format::LogFormatParser;
+use nginx_parser::normalize::{EndpointNormalizer, RouteRule};
+use nginx_parser::series::BucketWidth;
+use nginx_parser::sketch::DDSketch;
+use nginx_parser::time_range::{self, TimeRange};
+use nginx_parser::top::TopCounter;
+use nginx_parser::{combined, csv, follow, inputs, nginx_log, output, parallel, stats};
+use std::io::{BufRead, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-mod nginx_log;
-mod stats;
+/// How often to check for new lines with `--follow`
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The number of rows of each `--clients` table unless `--top` is given
+const DEFAULT_TOP_CLIENTS: usize = 10;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// A JSON document, see `output::JsonReport` for the schema
+    Json,
+    /// Prometheus text exposition format, for node_exporter's textfile collector. Per-file
+    /// stats are not included
+    Prometheus,
+    /// One JSON object per line for each time bucket, requires `--bucket`
+    Ndjson,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LineFormat {
+    /// The lines as they were read
+    Raw,
+    /// One JSON object per line, which can be read back as a JSON log
+    Json,
+    /// nginx's `combined` format
+    Combined,
+    /// CSV with a header row. Other `log_format` variables are left out
+    Csv,
+}
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
-    pub input: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// Also print the stats of each file on its own before the combined stats
+    #[arg(long)]
+    pub per_file: bool,
+    /// How to print the stats
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    /// Prefix for metric names with `--format prometheus`
+    #[arg(long, default_value = "nginx", value_parser = parse_metric_prefix)]
+    pub metric_prefix: String,
+    /// Label added to every metric with `--format prometheus`, can be given multiple times
+    #[arg(long, value_name = "NAME=VALUE", value_parser = output::parse_label)]
+    pub metric_label: Vec<(String, String)>,
+    /// Keep reading lines as they are appended to the log and print the stats periodically.
+    /// Follows the log across logrotate's rename and copytruncate rotations
+    #[arg(long, conflicts_with = "per_file")]
+    pub follow: bool,
+    /// Seconds between printing the stats with `--follow`
+    #[arg(long, default_value = "10", requires = "follow", value_parser = parse_interval)]
+    pub interval: Duration,
+    /// Number of threads to parse lines on. Results are the same for any number of threads,
+    /// except for approximate `--clients` counts. `--follow` reads lines on one thread as they
+    /// are appended
+    #[arg(long, default_value = "1", conflicts_with = "follow")]
+    pub threads: NonZeroUsize,
+    /// Always compute exact percentiles and `--clients` counts, keeping every response size and
+    /// client in memory. By default percentiles are estimated with a sketch once there are more
+    /// than a million responses, and clients counted approximately once there are more than
+    /// ten thousand
+    #[arg(long, conflicts_with = "accuracy")]
+    pub exact: bool,
+    /// Maximum relative error of estimated percentiles
+    #[arg(long, default_value_t = DDSketch::DEFAULT_RELATIVE_ACCURACY, value_parser = parse_accuracy)]
+    pub accuracy: f64,
+    /// Comma separated percentiles to report, e.g. `50,90,99,99.9,100` where 100 is the largest
+    /// value. Used for response sizes and timings, overall, per endpoint and per time bucket
+    #[arg(long, value_delimiter = ',', default_values_t = stats::LogStatsBuilder::DEFAULT_PERCENTILES, value_parser = parse_percentile)]
+    pub percentiles: Vec<f64>,
+    /// Also print a table of request counts, errors, status classes, and byte and time
+    /// percentiles for each endpoint
+    #[arg(long)]
+    pub endpoints: bool,
+    /// Column to sort the endpoint table by, highest first, e.g. `requests`, `errors`,
+    /// `error_rate`, `5xx`, `bytes_p99`, `time_p99` or `endpoint` for alphabetical order
+    #[arg(
+        long,
+        value_name = "COLUMN",
+        default_value = "requests",
+        requires = "endpoints"
+    )]
+    pub sort: EndpointColumn,
+    /// Only print the first N endpoints of the endpoint table, and the first N rows of each
+    /// `--clients` table instead of 10
+    #[arg(long, value_name = "N")]
+    pub top: Option<usize>,
+    /// Also print the client IPs, user agents and referrers with the most requests, bytes and
+    /// errors
+    #[arg(long)]
+    pub clients: bool,
+    /// Collapse numeric IDs, UUIDs and hashes in endpoints into `:id`, `:uuid` and `:hash`, so
+    /// `/users/8812/orders/77` is counted as `/users/:id/orders/:id`
+    #[arg(long)]
+    pub normalize: bool,
+    /// Rewrite endpoints matching a regex into a route template before counting them, e.g.
+    /// `^/files/.*=/files/:path`. The template can refer to capture groups as `${1}` or
+    /// `${name}` (`$1_x` is the group named `1_x`, so keep the braces). Can be given multiple
+    /// times, rules are applied in order before `--normalize`
+    #[arg(long, value_name = "PATTERN=TEMPLATE")]
+    pub route: Vec<RouteRule>,
+    /// Also compute the stats per time bucket of this width, e.g. `1m`, `5m` or `1h`, printed as
+    /// a table or with `--format ndjson` as one JSON object per bucket
+    #[arg(long, value_name = "WIDTH")]
+    pub bucket: Option<BucketWidth>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the lines matching `--filter` in order instead of stats, like a grep that understands
+    /// the log format
+    Filter(FilterArgs),
+}
+
+#[derive(Args)]
+struct FilterArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// How to print the matching lines
+    #[arg(long, value_enum, default_value_t = LineFormat::Raw)]
+    pub format: LineFormat,
+}
+
+// Options choosing which logs and lines to read, shared by every command. Not a doc comment,
+// which clap would show as the description of the commands it is flattened into
+#[derive(Args)]
+struct InputArgs {
+    /// Log files, directories or glob patterns to read, rotated files are read oldest first.
+    /// Reads standard input if none are given or for `-`
+    #[arg(default_value = inputs::STDIN)]
+    pub inputs: Vec<PathBuf>,
+    /// nginx `log_format` string the log was written with, detected from the log if not given
+    #[arg(long, conflicts_with = "nginx_conf")]
+    pub log_format: Option<String>,
+    /// nginx config file to read the log format from, see `--log-format-name`
+    #[arg(long)]
+    pub nginx_conf: Option<PathBuf>,
+    /// Name of the `log_format` in `--nginx-conf` the log was written with
+    #[arg(long, requires = "nginx_conf", default_value = "combined")]
+    pub log_format_name: String,
+    /// Skip lines that cannot be parsed instead of exiting, and report them at the end
+    #[arg(long)]
+    pub lenient: bool,
+    /// Exit if more than this many lines cannot be parsed, implies `--lenient`
+    #[arg(long)]
+    pub max_errors: Option<usize>,
+    /// Only read lines logged at or after this time, e.g. `2026-10-10T13:55:00+02:00`,
+    /// `"2026-10-10 13:55"` in local time, `13:55` today or `"2h ago"`. Uncompressed files are
+    /// binary searched for it, assuming they are sorted by time
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub since: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// Only read lines logged before this time, in the same formats as `--since`
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub until: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// Only read lines matching an expression, e.g.
+    /// `'status >= 500 && path ~ "^/api/" && ip in 10.0.0.0/8'`. See the README for the fields
+    /// and operators
+    #[arg(long, value_name = "EXPR", value_parser = parse_filter)]
+    pub filter: Option<Filter>,
+}
+
+fn parse_filter(filter: &str) -> Result<Filter, String> {
+    filter.parse().map_err(|e: FilterParseError| {
+        let indent = filter[..e.column].chars().count();
+        format!("{}\n\n  {}\n  {}^", e, filter, " ".repeat(indent))
+    })
+}
+
+fn parse_time(time: &str) -> Result<chrono::DateTime<chrono::FixedOffset>, String> {
+    time_range::parse_time(time, chrono::Local::now()).map_err(|e| e.to_string())
+}
+
+fn parse_percentile(percentile: &str) -> Result<f64, String> {
+    match percentile.trim().parse::<f64>() {
+        Ok(percentile) if (0.0..=100.0).contains(&percentile) => Ok(percentile),
+        _ => Err(format!(
+            "{:?} is not a number between 0 and 100",
+            percentile
+        )),
+    }
+}
+
+fn parse_accuracy(accuracy: &str) -> Result<f64, String> {
+    match accuracy.parse::<f64>() {
+        Ok(accuracy) if accuracy > 0.0 && accuracy < 1.0 => Ok(accuracy),
+        _ => Err(format!("{:?} is not a number between 0 and 1", accuracy)),
+    }
+}
+
+fn parse_interval(interval: &str) -> Result<Duration, String> {
+    match interval.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 => Duration::try_from_secs_f64(seconds)
+            .ok()
+            .filter(|interval| !interval.is_zero())
+            .ok_or_else(|| format!("{:?} is out of range for an interval", interval)),
+        _ => Err(format!(
+            "{:?} is not a positive number of seconds",
+            interval
+        )),
+    }
+}
+
+fn parse_metric_prefix(prefix: &str) -> Result<String, String> {
+    if output::is_valid_metric_name(prefix) {
+        Ok(prefix.to_owned())
+    } else {
+        Err(format!("{:?} is not a valid metric name", prefix))
+    }
+}
+
+impl InputArgs {
+    /// Returns the log format given on the command line, if any
+    fn log_format(&self) -> Result<Option<nginx_log::LogFormat>, Box<dyn std::error::Error>> {
+        let parser = if let Some(format) = &self.log_format {
+            LogFormatParser::new(format)?
+        } else if let Some(config) = &self.nginx_conf {
+            LogFormatParser::from_config(config, &self.log_format_name)?
+        } else {
+            return Ok(None);
+        };
+
+        Ok(Some(nginx_log::LogFormat::Custom(parser)))
+    }
+
+    /// Returns the time range to read lines from
+    fn time_range(&self) -> TimeRange {
+        TimeRange::new(self.since, self.until)
+    }
+
+    /// Checks the options and finds what to read, exiting with an error if that fails
+    ///
+    /// # Returns
+    ///
+    /// The log format given on the command line, the files to read, and the report to record
+    /// malformed lines in
+    fn resolve(
+        &self,
+    ) -> (
+        Option<nginx_log::LogFormat>,
+        Vec<PathBuf>,
+        nginx_log::ErrorReport,
+    ) {
+        if let (Some(since), Some(until)) = (self.since, self.until) {
+            if since >= until {
+                eprintln!("--since must be before --until");
+                std::process::exit(1);
+            }
+        }
+
+        let format = match self.log_format() {
+            Ok(format) => format,
+            Err(e) => {
+                eprintln!("Error reading log format: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        let files = match inputs::expand(&self.inputs) {
+            Ok(files) => files,
+            Err(e) => {
+                eprintln!("Error finding log files: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        let report = if self.lenient || self.max_errors.is_some() {
+            nginx_log::ErrorReport::lenient(self.max_errors)
+        } else {
+            nginx_log::ErrorReport::strict()
+        };
+
+        (format, files, report)
+    }
+
+    /// Opens a log file, or standard input for [`inputs::STDIN`], to read the lines in the time
+    /// range from, exiting with an error if that fails
+    fn open(
+        &self,
+        file: &Path,
+        format: Option<nginx_log::LogFormat>,
+    ) -> nginx_log::NginxLogReader<Box<dyn BufRead + Send>> {
+        let reader = if file == Path::new(inputs::STDIN) {
+            nginx_log::NginxLogReader::from_stdin(format)
+                .map(|reader| reader.time_range(self.time_range()))
+        } else {
+            nginx_log::NginxLogReader::from_path_in_range(file, format, self.time_range())
+        };
+
+        match reader {
+            Ok(reader) => reader,
+            Err(e) => exit_with_error(file, e.into(), self.max_errors),
+        }
+    }
+}
+
+impl Cli {
+    /// Returns an empty stats builder computing percentiles as chosen on the command line
+    fn stats_builder(&self) -> stats::LogStatsBuilder {
+        let builder = if self.exact {
+            stats::LogStatsBuilder::exact()
+        } else {
+            stats::LogStatsBuilder::with_relative_accuracy(self.accuracy)
+        };
+        let mut builder = builder.percentiles(&self.percentiles);
+        if self.normalize || !self.route.is_empty() {
+            let normalizer = EndpointNormalizer::new(self.normalize, self.route.clone());
+            builder = builder.normalizer(normalizer);
+        }
+        if self.endpoints {
+            builder = builder.per_endpoint();
+        }
+        if let Some(width) = self.bucket {
+            builder = builder.bucketed(width);
+        }
+        if let Some(filter) = &self.input.filter {
+            builder = builder.filter(filter.clone());
+        }
+        if self.clients {
+            let capacity = (!self.exact).then_some(TopCounter::DEFAULT_CAPACITY);
+            builder = builder.top_clients(self.top.unwrap_or(DEFAULT_TOP_CLIENTS), capacity);
+        }
+        builder
+    }
+
+    /// Builds the stats, sorting and limiting the endpoint table as chosen on the command line
+    fn build_stats(&self, builder: stats::LogStatsBuilder) -> stats::LogStats {
+        let mut stats = builder.build();
+        stats.sort_endpoints(&self.sort, self.top);
+        stats
+    }
 }
 
 fn main() {
     let args = Cli::parse();
+    if let Some(Command::Filter(args)) = &args.command {
+        filter_lines(args);
+        return;
+    }
 
-    let log = match nginx_log::NginxLog::from_path(&args.input) {
-        Ok(log) => log,
-        Err(e) => {
-            eprintln!("Error reading log file: {}", e);
+    if matches!(args.format, OutputFormat::Ndjson) && args.bucket.is_none() {
+        eprintln!("--format ndjson requires --bucket");
+        std::process::exit(1);
+    }
+    if args.top.is_some() && !args.endpoints && !args.clients {
+        eprintln!("--top requires --endpoints or --clients");
+        std::process::exit(1);
+    }
+    if let Some(percentile) = args.sort.percentile() {
+        if !args.percentiles.contains(&percentile) {
+            eprintln!(
+                "Cannot sort by the {}th percentile, it is not in --percentiles",
+                percentile
+            );
             std::process::exit(1);
         }
+    }
+
+    let (format, files, mut report) = args.input.resolve();
+
+    if args.follow {
+        follow(&args, &files, format, report);
+    }
+
+    let mut builder = args.stats_builder();
+    let mut file_stats = Vec::new();
+    for file in &files {
+        let mut file_builder = args.stats_builder();
+
+        let mut reader = args.input.open(file, format.clone());
+
+        if args.threads.get() > 1 {
+            let aggregated = parallel::aggregate(
+                &mut reader,
+                args.threads,
+                &mut file_builder,
+                &mut report,
+                Some(file),
+            );
+            if let Err(e) = aggregated {
+                exit_with_error(file, e, args.input.max_errors);
+            }
+        } else {
+            read_lines(
+                &mut reader,
+                file,
+                &mut file_builder,
+                &mut report,
+                args.input.max_errors,
+            );
+        }
+
+        if args.per_file {
+            file_stats.push((file.clone(), args.build_stats(file_builder.clone())));
+        }
+        builder.merge(file_builder);
+    }
+    let stats = args.build_stats(builder);
+
+    print_stats(&args, &stats, &file_stats, &report, false);
+}
+
+/// Reads a single growing log file forever, printing the stats every `--interval` seconds
+fn follow(
+    args: &Cli,
+    files: &[PathBuf],
+    format: Option<nginx_log::LogFormat>,
+    mut report: nginx_log::ErrorReport,
+) -> ! {
+    let [file] = files else {
+        eprintln!("--follow requires exactly one log file");
+        std::process::exit(1);
     };
+    if file == Path::new(inputs::STDIN) {
+        eprintln!("--follow cannot read from stdin");
+        std::process::exit(1);
+    }
+
+    let follower = match follow::LogFollower::open(file) {
+        Ok(follower) => follower,
+        Err(e) => exit_with_error(file, e.into(), args.input.max_errors),
+    };
+    let mut reader = nginx_log::NginxLogReader::new(std::io::BufReader::new(follower), format)
+        .time_range(args.input.time_range());
+
+    let interval = args.interval;
+    let mut builder = args.stats_builder();
+    let mut last_print = None;
+    loop {
+        read_lines(
+            &mut reader,
+            file,
+            &mut builder,
+            &mut report,
+            args.input.max_errors,
+        );
+
+        if last_print.is_none_or(|last: Instant| last.elapsed() >= interval) {
+            if let OutputFormat::Text = args.format {
+                let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+                exit_on_write_error(writeln!(std::io::stdout(), "==> {} <==", time), "stats");
+            }
+            print_stats(args, &args.build_stats(builder.clone()), &[], &report, true);
+            last_print = Some(Instant::now());
+        }
+
+        std::thread::sleep(FOLLOW_POLL_INTERVAL.min(interval));
+    }
+}
+
+/// Prints the lines matching `--filter` from every file in order, in the chosen format
+///
+/// Malformed lines are reported on standard error, after the lines.
+fn filter_lines(args: &FilterArgs) {
+    let (format, files, mut report) = args.input.resolve();
+
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let written =
+        write_lines(args, format, &files, &mut report, &mut out).and_then(|_| out.flush());
+    exit_on_write_error(written, "lines");
+
+    if report.count > 0 {
+        eprint!("{report}");
+    }
+}
+
+/// Writes the lines matching `--filter` from every file to `out`
+///
+/// # Errors
+///
+/// Returns an error if writing to `out` fails
+fn write_lines(
+    args: &FilterArgs,
+    format: Option<nginx_log::LogFormat>,
+    files: &[PathBuf],
+    report: &mut nginx_log::ErrorReport,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    if let LineFormat::Csv = args.format {
+        writeln!(out, "{}", csv::HEADER)?;
+    }
+
+    // Lines already matched are flushed before exiting on an error
+    for file in files {
+        out.flush()?;
+        let mut reader = args.input.open(file, format.clone());
+        while let Some(line) = reader.next() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    if let Err(e) = report.record(Some(file), e) {
+                        out.flush()?;
+                        exit_with_error(file, e, args.input.max_errors);
+                    }
+                    continue;
+                }
+            };
+            if args
+                .input
+                .filter
+                .as_ref()
+                .is_some_and(|f| !f.matches(&line))
+            {
+                continue;
+            }
+
+            match args.format {
+                LineFormat::Raw => writeln!(out, "{}", reader.raw_line())?,
+                LineFormat::Json => {
+                    let json = serde_json::to_string(&line).expect("lines serialize to JSON");
+                    writeln!(out, "{}", json)?;
+                }
+                LineFormat::Combined => writeln!(out, "{}", combined::format_line(&line))?,
+                LineFormat::Csv => writeln!(out, "{}", csv::format_line(&line))?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds every line available from a reader to the stats
+///
+/// Lines that cannot be parsed are recorded in the report, exiting if it does not allow them.
+fn read_lines<R: BufRead>(
+    reader: &mut nginx_log::NginxLogReader<R>,
+    file: &Path,
+    builder: &mut stats::LogStatsBuilder,
+    report: &mut nginx_log::ErrorReport,
+    max_errors: Option<usize>,
+) {
+    for line in reader {
+        let error = match line {
+            Ok(line) => {
+                builder.add(&line);
+                continue;
+            }
+            Err(e) => e,
+        };
+
+        if let Err(e) = report.record(Some(file), error) {
+            exit_with_error(file, e, max_errors);
+        }
+    }
+}
+
+/// Prints the stats in the format chosen on the command line
+///
+/// `stream` prints JSON on a single line, for when several reports are printed one after another.
+/// Exits quietly if the reader of standard output, such as `head`, closes it early.
+fn print_stats(
+    args: &Cli,
+    stats: &stats::LogStats,
+    file_stats: &[(PathBuf, stats::LogStats)],
+    report: &nginx_log::ErrorReport,
+    stream: bool,
+) {
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let written =
+        write_stats(args, stats, file_stats, report, stream, &mut out).and_then(|_| out.flush());
+    exit_on_write_error(written, "stats");
+}
+
+/// Writes the stats in the format chosen on the command line, see [`print_stats`]
+fn write_stats(
+    args: &Cli,
+    stats: &stats::LogStats,
+    file_stats: &[(PathBuf, stats::LogStats)],
+    report: &nginx_log::ErrorReport,
+    stream: bool,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    match args.format {
+        OutputFormat::Text => {
+            for (file, stats) in file_stats {
+                writeln!(out, "==> {} <==", file.display())?;
+                writeln!(out, "{stats}")?;
+            }
+            if args.per_file {
+                writeln!(out, "==> total <==")?;
+            }
+            writeln!(out, "{stats}")?;
+            if report.count > 0 {
+                write!(out, "{report}")?;
+            }
+        }
+        OutputFormat::Json => {
+            let json = output::JsonReport::new(stats, file_stats, report);
+            if stream {
+                writeln!(out, "{}", json.to_json_line())?;
+            } else {
+                writeln!(out, "{}", json.to_json())?;
+            }
+        }
+        OutputFormat::Ndjson => {
+            write!(
+                out,
+                "{}",
+                output::to_ndjson(stats.buckets.as_deref().unwrap_or_default())
+            )?;
+        }
+        OutputFormat::Prometheus => {
+            let options = output::PrometheusOptions {
+                prefix: args.metric_prefix.clone(),
+                labels: args.metric_label.clone(),
+            };
+            write!(out, "{}", output::PrometheusReport::new(stats, &options))?;
+        }
+    }
+    Ok(())
+}
+
+/// Exits if writing the output failed, quietly if its reader has closed it
+fn exit_on_write_error(written: std::io::Result<()>, what: &str) {
+    match written {
+        Ok(()) => {}
+        // The reader of the output, such as `head`, has seen enough
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(e) => {
+            eprintln!("Error writing {}: {}", what, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints an error from reading a log file and exits
+fn exit_with_error(file: &Path, error: nginx_log::ReadError, max_errors: Option<usize>) -> ! {
+    match (&error, max_errors) {
+        (nginx_log::ReadError::Parse { .. }, Some(max)) => eprintln!(
+            "More than {} malformed lines, last error: {}: {}",
+            max,
+            file.display(),
+            error
+        ),
+        _ => eprintln!("Error reading log file {}: {}", file.display(), error),
+    }
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = concat!(
+        r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "GET /a HTTP/1.1" 200 10 "-" "curl""#,
+        "\n",
+        r#"10.0.0.2 - - [10/Oct/2026:13:55:37 +0000] "GET /b?q=\x22x\x22 HTTP/1.1" 502 20 "#,
+        r#""-" "a \"b\"""#,
+        "\nmalformed\n",
+        r#"10.0.0.3 - - [10/Oct/2026:13:55:38 +0000] "POST /c HTTP/1.1" 503 30 "-" "curl""#,
+        "\r\n",
+    );
+
+    /// Runs the filter command with `args` over [`LOG`], returning what it wrote and its report
+    fn filter(name: &str, args: &[&str]) -> (String, nginx_log::ErrorReport) {
+        let path = std::env::temp_dir().join(format!(
+            "nginx-parser-filter-{}-{}.log",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, LOG).unwrap();
+
+        let mut argv = vec!["nginx-parser", "filter", "--lenient"];
+        argv.extend(args);
+        argv.push(path.to_str().unwrap());
+        let Some(Command::Filter(args)) = Cli::try_parse_from(argv).unwrap().command else {
+            panic!("expected the filter command");
+        };
+        let (format, files, mut report) = args.input.resolve();
+        let mut out = Vec::new();
+        write_lines(&args, format, &files, &mut report, &mut out).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        (String::from_utf8(out).unwrap(), report)
+    }
+
+    fn raw_lines(numbers: &[usize]) -> Vec<&'static str> {
+        let lines = LOG.lines().map(|line| line.trim_end_matches('\r'));
+        let lines = lines.collect::<Vec<_>>();
+        numbers.iter().map(|number| lines[number - 1]).collect()
+    }
+
+    #[test]
+    fn writes_matching_lines_in_each_format() {
+        let matching = raw_lines(&[2, 4]);
+        let parsed = matching
+            .iter()
+            .map(|line| combined::parse_line(line).unwrap())
+            .collect::<Vec<_>>();
+
+        let (raw, report) = filter("raw", &["--filter", "status >= 500"]);
+        assert_eq!(raw.lines().collect::<Vec<_>>(), matching);
+        assert_eq!(report.count, 1);
+
+        let (json, _) = filter("json", &["--filter", "status >= 500", "--format", "json"]);
+        let json = json
+            .lines()
+            .map(|line| serde_json::from_str::<nginx_log::NginxLogLine>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(json, parsed);
+
+        let args = ["--filter", "status >= 500", "--format", "combined"];
+        let (formatted, _) = filter("combined", &args);
+        assert_eq!(
+            formatted.lines().collect::<Vec<_>>(),
+            [
+                concat!(
+                    r#"10.0.0.2 - - [10/Oct/2026:13:55:37 +0000] "GET /b?q=\x22x\x22 HTTP/1.1" "#,
+                    r#"502 20 "-" "a \x22b\x22""#
+                ),
+                matching[1],
+            ]
+        );
+        let reparsed = formatted
+            .lines()
+            .map(|line| combined::parse_line(line).unwrap());
+        assert_eq!(reparsed.collect::<Vec<_>>(), parsed);
+
+        let (rows, _) = filter("csv", &["--filter", "status >= 500", "--format", "csv"]);
+        assert_eq!(
+            rows.lines().collect::<Vec<_>>(),
+            [
+                csv::HEADER,
+                r#"2026-10-10T13:55:37+00:00,10.0.0.2,-,"GET /b?q=""x"" HTTP/1.1",502,20,-,"a ""b""",,"#,
+                "2026-10-10T13:55:38+00:00,10.0.0.3,-,POST /c HTTP/1.1,503,30,-,curl,,",
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_every_line_in_the_time_range() {
+        let (raw, report) = filter("all", &[]);
+        assert_eq!(raw.lines().collect::<Vec<_>>(), raw_lines(&[1, 2, 4]));
+        assert_eq!(report.count, 1);
+
+        let args = ["--since", "2026-10-10T13:55:37+00:00"];
+        let (raw, report) = filter("since", &args);
+        assert_eq!(raw.lines().collect::<Vec<_>>(), raw_lines(&[2, 4]));
+        assert_eq!(report.count, 1);
 
-    let stats = stats::LogStats::from_nginx_log(&log);
+        let args = ["--until", "2026-10-10T13:55:37+00:00"];
+        let (raw, report) = filter("until", &args);
+        assert_eq!(raw.lines().collect::<Vec<_>>(), raw_lines(&[1]));
+        assert_eq!(report.count, 0);
 
-    println!("{stats}")
+        let args = ["--filter", "status == 404", "--format", "csv"];
+        let (rows, report) = filter("none", &args);
+        assert_eq!(rows, format!("{}\n", csv::HEADER));
+        assert_eq!(report.count, 1);
+    }
 }
@@ -1,11 +1,31 @@
 /// Contains structures representing Nginx log file lines
-use serde::Deserialize;
-use std::io::BufRead;
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::{BufRead, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::Utf8Error;
+use std::time::Duration;
+
+use crate::compression::{self, Compression};
+use crate::duration;
+use crate::log_format::LogFormatParser;
+use crate::request::{MalformedRequest, Request, MALFORMED_ENDPOINT};
+use crate::time_range::{self, TimeRange};
+use crate::timestamp;
 
 /// Represents a single line in an Nginx log file
-#[derive(Debug, Clone, Deserialize)]
+///
+/// Serializes to the JSON it can be deserialized from.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct NginxLogLine {
-    pub time: String,
+    /// When the request was logged, see [`timestamp::parse`] for the accepted formats
+    #[serde(
+        deserialize_with = "timestamp::deserialize",
+        serialize_with = "timestamp::serialize"
+    )]
+    pub time: DateTime<FixedOffset>,
     pub remote_ip: String,
     pub remote_user: String,
     pub request: String,
@@ -13,6 +33,640 @@ pub struct NginxLogLine {
     pub bytes: u64,
     pub referrer: String,
     pub agent: String,
+    /// Time spent processing the request, from `$request_time`
+    #[serde(
+        default,
+        deserialize_with = "duration::deserialize",
+        serialize_with = "duration::serialize"
+    )]
+    pub request_time: Option<Duration>,
+    /// Total time spent waiting for upstream servers, from `$upstream_response_time`. `None` if
+    /// the request was not passed to an upstream, see [`duration::parse`]
+    #[serde(
+        default,
+        deserialize_with = "duration::deserialize",
+        serialize_with = "duration::serialize"
+    )]
+    pub upstream_response_time: Option<Duration>,
+    /// Any other values on the line, keyed by their JSON key or `log_format` variable name
+    #[serde(flatten, deserialize_with = "deserialize_extra")]
+    pub extra: BTreeMap<String, String>,
+}
+
+impl NginxLogLine {
+    /// Parses the request line into its method, path, query parameters and HTTP version
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request line is not valid HTTP, see [`Request::parse`]
+    pub fn request_line(&self) -> Result<Request, MalformedRequest> {
+        Request::parse(&self.request)
+    }
+
+    /// Returns the decoded path of the request, or [`MALFORMED_ENDPOINT`] if the request line
+    /// is not valid HTTP
+    pub fn endpoint(&self) -> String {
+        self.request_line()
+            .map(|request| request.path)
+            .unwrap_or_else(|_| MALFORMED_ENDPOINT.to_owned())
+    }
+}
+
+/// Deserializes unknown JSON values as strings, so numbers and booleans are kept as written
+fn deserialize_extra<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let values = BTreeMap::<String, serde_json::Value>::deserialize(deserializer)?;
+    Ok(values
+        .into_iter()
+        .map(|(key, value)| match value {
+            serde_json::Value::String(value) => (key, value),
+            value => (key, value.to_string()),
+        })
+        .collect())
+}
+
+/// The on-disk format of an Nginx log file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One JSON object per line, as written by a JSON `log_format`
+    Json,
+    /// nginx's predefined `combined` format, or the shorter `common` format
+    Combined,
+    /// A custom `log_format`
+    Custom(LogFormatParser),
+}
+
+impl LogFormat {
+    /// Guesses the format of a log from one of its lines
+    ///
+    /// Lines starting with `{` are treated as JSON, anything else as `combined`.
+    pub fn detect(line: &str) -> Self {
+        if line.trim_start().starts_with('{') {
+            Self::Json
+        } else {
+            Self::Combined
+        }
+    }
+
+    /// Parses a single line in this format
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - A single log line without its trailing newline
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line is not valid in this format
+    pub fn parse_line(
+        &self,
+        line: &str,
+    ) -> Result<NginxLogLine, Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            Self::Json => Ok(serde_json::from_str(line)?),
+            Self::Combined => Ok(crate::combined::parse_line(line)?),
+            Self::Custom(parser) => Ok(parser.parse_line(line)?),
+        }
+    }
+
+    /// Parses only the time of a single line in this format
+    ///
+    /// This is much cheaper than [`LogFormat::parse_line`], to skip lines outside a time range
+    /// without parsing them.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - A single log line without its trailing newline
+    ///
+    /// # Returns
+    ///
+    /// The time of the line, or `None` if it cannot be parsed
+    pub fn parse_time(&self, line: &str) -> Option<DateTime<FixedOffset>> {
+        #[derive(Deserialize)]
+        struct Time {
+            #[serde(deserialize_with = "timestamp::deserialize")]
+            time: DateTime<FixedOffset>,
+        }
+
+        match self {
+            Self::Json => serde_json::from_str::<Time>(line).ok().map(|t| t.time),
+            Self::Combined => crate::combined::parse_time(line),
+            Self::Custom(parser) => parser.parse_time(line),
+        }
+    }
+}
+
+/// Error returned while reading lines from an Nginx log
+#[derive(Debug)]
+pub enum ReadError {
+    /// The underlying reader failed
+    Io(std::io::Error),
+    /// A line could not be parsed
+    Parse {
+        /// 1-based line number, unknown if the reader started in the middle of the log
+        line: Option<usize>,
+        /// Byte offset of the start of the line, after decompression
+        offset: u64,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Parse {
+                line: Some(line),
+                source,
+                ..
+            } => write!(f, "line {}: {}", line, source),
+            Self::Parse {
+                line: None,
+                offset,
+                source,
+            } => write!(f, "offset {}: {}", offset, source),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+impl From<std::io::Error> for ReadError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// A line that could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MalformedLine {
+    /// The file the line was read from, if known
+    pub file: Option<PathBuf>,
+    /// 1-based line number, unknown if the reader started in the middle of the log
+    pub line: Option<usize>,
+    /// Byte offset of the start of the line, after decompression
+    pub offset: u64,
+    pub error: String,
+}
+
+/// Tracks lines that failed to parse, deciding whether reading can continue
+///
+/// In strict mode the first malformed line is an error. In lenient mode malformed lines are
+/// counted and skipped, optionally up to a maximum, and the first few are kept as examples.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorReport {
+    /// Number of malformed lines seen
+    pub count: usize,
+    /// The first malformed lines seen, up to `max_examples`
+    pub examples: Vec<MalformedLine>,
+    #[serde(skip)]
+    max_errors: Option<usize>,
+    #[serde(skip)]
+    max_examples: usize,
+}
+
+impl ErrorReport {
+    /// The number of malformed lines kept as examples by default
+    pub const DEFAULT_EXAMPLES: usize = 5;
+
+    /// Creates an ErrorReport that rejects the first malformed line
+    pub fn strict() -> Self {
+        Self::lenient(Some(0))
+    }
+
+    /// Creates an ErrorReport that skips malformed lines
+    ///
+    /// # Arguments
+    ///
+    /// * `max_errors` - The number of malformed lines to tolerate, or `None` for no limit
+    pub fn lenient(max_errors: Option<usize>) -> Self {
+        Self {
+            count: 0,
+            examples: Vec::new(),
+            max_errors,
+            max_examples: Self::DEFAULT_EXAMPLES,
+        }
+    }
+
+    /// Sets how many malformed lines are kept as examples
+    pub fn with_max_examples(mut self, max_examples: usize) -> Self {
+        self.max_examples = max_examples;
+        self
+    }
+
+    /// Records an error from an [`NginxLogReader`]
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file the reader was reading, if known
+    /// * `error` - The error returned by the reader
+    ///
+    /// # Errors
+    ///
+    /// Returns the error back if it is an I/O error, or if it takes the number of malformed
+    /// lines over the maximum
+    pub fn record(&mut self, file: Option<&Path>, error: ReadError) -> Result<(), ReadError> {
+        let ReadError::Parse {
+            line,
+            offset,
+            source,
+        } = &error
+        else {
+            return Err(error);
+        };
+
+        self.count += 1;
+        if self.max_errors.is_some_and(|max| self.count > max) {
+            return Err(error);
+        }
+
+        if self.examples.len() < self.max_examples {
+            self.examples.push(MalformedLine {
+                file: file.map(Path::to_owned),
+                line: *line,
+                offset: *offset,
+                error: source.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for ErrorReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Malformed Lines: {}", self.count)?;
+        for example in &self.examples {
+            write!(f, "  ")?;
+            if let Some(file) = &example.file {
+                write!(f, "{} ", file.display())?;
+            }
+            match example.line {
+                Some(line) => write!(f, "Line {} (offset {})", line, example.offset)?,
+                None => write!(f, "Offset {}", example.offset)?,
+            }
+            writeln!(f, ": {}", example.error)?;
+        }
+        if self.count > self.examples.len() {
+            writeln!(f, "  ... and {} more", self.count - self.examples.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Lazily parses lines from an Nginx log as they are read
+///
+/// Only the current line is held in memory, so arbitrarily large logs can be processed. Lines
+/// outside the reader's [`TimeRange`] are skipped without being fully parsed.
+pub struct NginxLogReader<R> {
+    reader: R,
+    format: Option<LogFormat>,
+    buf: String,
+    /// Why the line in `buf`, decoded lossily, is not valid UTF-8
+    utf8_error: Option<Utf8Error>,
+    /// The number of lines read, unknown if the reader started in the middle of the log
+    line: Option<usize>,
+    offset: u64,
+    range: TimeRange,
+    /// Whether the last line whose time could be parsed was in the time range
+    inside: bool,
+}
+
+impl<R: BufRead> NginxLogReader<R> {
+    /// Creates a new NginxLogReader over any buffered reader
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader to read log lines from
+    /// * `format` - The format of the log, or `None` to detect it from the first line
+    pub fn new(reader: R, format: Option<LogFormat>) -> Self {
+        Self {
+            reader,
+            format,
+            buf: String::new(),
+            utf8_error: None,
+            line: Some(0),
+            offset: 0,
+            range: TimeRange::default(),
+            inside: true,
+        }
+    }
+
+    /// Skips lines logged outside a time range
+    ///
+    /// Only the time of each line is parsed to decide, see [`LogFormat::parse_time`]. Lines
+    /// whose time cannot be parsed are returned, so that they are reported as malformed, only
+    /// once the window has been reached: when the last line whose time could be parsed was in
+    /// the range, or before any such line if the range has no start.
+    pub fn time_range(mut self, range: TimeRange) -> Self {
+        self.range = range;
+        self.inside = range.since.is_none();
+        self
+    }
+
+    /// Returns the format of the log, if it has been given or detected yet
+    pub fn format(&self) -> Option<&LogFormat> {
+        self.format.as_ref()
+    }
+
+    /// Returns the text of the line last returned by the iterator, without its line ending
+    ///
+    /// Lines that are not valid UTF-8 are decoded lossily, but are only returned as errors.
+    pub fn raw_line(&self) -> &str {
+        &self.buf
+    }
+}
+
+impl NginxLogReader<Box<dyn BufRead + Send>> {
+    /// Creates a new NginxLogReader over the file at the given path
+    ///
+    /// gzip, bzip2 and zstd compressed files are detected and decompressed as they are read,
+    /// see [`compression::decompress`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the Nginx log file
+    /// * `format` - The format of the log, or `None` to detect it from the first line
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened or its start cannot be read
+    pub fn from_path<P>(path: P, format: Option<LogFormat>) -> std::io::Result<Self>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let file = std::fs::File::open(path)?;
+        let reader = compression::decompress(std::io::BufReader::new(file))?;
+        Ok(Self::new(reader, format))
+    }
+
+    /// Creates a new NginxLogReader over the lines of the file at the given path logged in a
+    /// time range
+    ///
+    /// If the file is uncompressed and the range has a start, the reader jumps to the first
+    /// line logged at or after it with [`time_range::seek_since`] instead of reading the whole
+    /// file up to it. This assumes the file is sorted by time, as nginx writes it. Line numbers
+    /// are unknown after a jump, so errors only give the offset of malformed lines. Otherwise
+    /// this is the same as [`Self::from_path`] with [`Self::time_range`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the Nginx log file
+    /// * `format` - The format of the log, or `None` to detect it from the first line
+    /// * `range` - The time range to read lines from
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened, or its start or the lines it is searched
+    /// through cannot be read
+    pub fn from_path_in_range<P>(
+        path: P,
+        format: Option<LogFormat>,
+        range: TimeRange,
+    ) -> std::io::Result<Self>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let file = std::fs::File::open(path)?;
+        let seekable = file.metadata()?.is_file();
+        let mut file = std::io::BufReader::new(file);
+        let since = match range.since {
+            Some(since)
+                if seekable && Compression::detect(file.fill_buf()?) == Compression::None =>
+            {
+                since
+            }
+            _ => return Ok(Self::new(compression::decompress(file)?, format).time_range(range)),
+        };
+
+        let format = match format {
+            Some(format) => format,
+            None => {
+                let mut first = Vec::new();
+                file.read_until(b'\n', &mut first)?;
+                LogFormat::detect(&String::from_utf8_lossy(&first))
+            }
+        };
+        let offset = time_range::seek_since(&mut file, &format, &since)?;
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut reader = Self::new(Box::new(file) as Box<dyn BufRead + Send>, Some(format));
+        reader.line = Some(0).filter(|_| offset == 0);
+        reader.offset = offset;
+        Ok(reader.time_range(range))
+    }
+
+    /// Creates a new NginxLogReader over standard input
+    ///
+    /// Compressed input is detected and decompressed in the same way as [`Self::from_path`].
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format of the log, or `None` to detect it from the first line
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the start of the input cannot be read
+    pub fn from_stdin(format: Option<LogFormat>) -> std::io::Result<Self> {
+        let reader = compression::decompress(std::io::BufReader::new(std::io::stdin()))?;
+        Ok(Self::new(reader, format))
+    }
+}
+
+impl<R: BufRead> NginxLogReader<R> {
+    /// Reads the next line into `buf` without its line ending, detecting the format if needed
+    ///
+    /// A line that is not valid UTF-8 is decoded lossily and `utf8_error` set, so that it is
+    /// reported as malformed instead of failing the whole read. Returns the line number and
+    /// offset of the line, or `None` at the end of the log.
+    fn read_raw(&mut self) -> Option<std::io::Result<(Option<usize>, u64)>> {
+        let mut bytes = std::mem::take(&mut self.buf).into_bytes();
+        bytes.clear();
+        let read = match self.reader.read_until(b'\n', &mut bytes) {
+            Ok(0) => return None,
+            Ok(read) => read,
+            Err(e) => return Some(Err(e)),
+        };
+        (self.buf, self.utf8_error) = match String::from_utf8(bytes) {
+            Ok(text) => (text, None),
+            Err(e) => (
+                String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                Some(e.utf8_error()),
+            ),
+        };
+        let offset = self.offset;
+        self.offset += read as u64;
+        self.line = self.line.map(|line| line + 1);
+
+        let len = self.buf.strip_suffix('\n').map_or(self.buf.len(), str::len);
+        let len = self.buf[..len].strip_suffix('\r').map_or(len, str::len);
+        self.buf.truncate(len);
+        if self.format.is_none() {
+            self.format = Some(LogFormat::detect(&self.buf));
+        }
+
+        Some(Ok((self.line, offset)))
+    }
+
+    /// Reads up to `max_lines` lines without parsing them
+    ///
+    /// This allows lines to be parsed on other threads, see [`crate::parallel`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying reader fails
+    pub fn next_batch(&mut self, max_lines: usize) -> std::io::Result<Option<LineBatch>> {
+        let mut lines = Vec::new();
+        while lines.len() < max_lines {
+            let Some(read) = self.read_raw() else {
+                break;
+            };
+            let (line, offset) = read?;
+            lines.push(RawLine {
+                text: std::mem::take(&mut self.buf),
+                utf8_error: self.utf8_error,
+                line,
+                offset,
+            });
+        }
+
+        // Only the last line whose time can be parsed decides where the next batch starts
+        let inside = self.inside;
+        if let Some(format) = self.format.as_ref().filter(|_| !self.range.is_unbounded()) {
+            if let Some(time) = lines
+                .iter()
+                .rev()
+                .find_map(|raw| format.parse_time(&raw.text))
+            {
+                self.inside = self.range.contains(&time);
+            }
+        }
+
+        Ok(self
+            .format
+            .clone()
+            .filter(|_| !lines.is_empty())
+            .map(|format| LineBatch {
+                format,
+                lines,
+                range: self.range,
+                inside,
+            }))
+    }
+}
+
+impl<R: BufRead> Iterator for NginxLogReader<R> {
+    type Item = Result<NginxLogLine, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (line, offset, format) = loop {
+            let (line, offset) = match self.read_raw()? {
+                Ok(read) => read,
+                Err(e) => return Some(Err(e.into())),
+            };
+            let format = self
+                .format
+                .as_ref()
+                .expect("format is detected by read_raw");
+            if keep(&self.range, format, &self.buf, &mut self.inside) {
+                break (line, offset, format);
+            }
+        };
+
+        Some(parse_raw(format, &self.buf, self.utf8_error, line, offset))
+    }
+}
+
+/// Parses a line read by an [`NginxLogReader`], which is malformed if it is not valid UTF-8
+fn parse_raw(
+    format: &LogFormat,
+    text: &str,
+    utf8_error: Option<Utf8Error>,
+    line: Option<usize>,
+    offset: u64,
+) -> Result<NginxLogLine, ReadError> {
+    let parsed = match utf8_error {
+        Some(e) => Err(e.into()),
+        None => format.parse_line(text),
+    };
+    parsed.map_err(|source| ReadError::Parse {
+        line,
+        offset,
+        source,
+    })
+}
+
+/// Returns whether a line is kept by a time range, parsing only its time
+///
+/// A line whose time cannot be parsed is kept if `inside` is set, which is then updated to
+/// whether the line is in the range if its time can be parsed. This keeps the same lines whether
+/// or not the start of the range was found with [`time_range::seek_since`].
+fn keep(range: &TimeRange, format: &LogFormat, line: &str, inside: &mut bool) -> bool {
+    if range.is_unbounded() {
+        return true;
+    }
+    if let Some(time) = format.parse_time(line) {
+        *inside = range.contains(&time);
+    }
+    *inside
+}
+
+#[derive(Debug, Clone)]
+struct RawLine {
+    text: String,
+    utf8_error: Option<Utf8Error>,
+    line: Option<usize>,
+    offset: u64,
+}
+
+/// Consecutive lines read from an Nginx log, to be parsed separately
+#[derive(Debug, Clone)]
+pub struct LineBatch {
+    format: LogFormat,
+    lines: Vec<RawLine>,
+    range: TimeRange,
+    /// Whether the reader was inside the time range before the first line
+    inside: bool,
+}
+
+impl LineBatch {
+    /// Returns the number of lines in the batch
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns whether the batch has no lines
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Parses the lines in the batch, with the same results an [`NginxLogReader`] would give
+    ///
+    /// Lines outside the time range of the reader are skipped.
+    pub fn parse(&self) -> impl Iterator<Item = Result<NginxLogLine, ReadError>> + '_ {
+        let mut inside = self.inside;
+        self.lines
+            .iter()
+            .filter(move |raw| keep(&self.range, &self.format, &raw.text, &mut inside))
+            .map(|raw| {
+                parse_raw(
+                    &self.format,
+                    &raw.text,
+                    raw.utf8_error,
+                    raw.line,
+                    raw.offset,
+                )
+            })
+    }
 }
 
 /// Represents an entire Nginx log file
@@ -22,28 +676,171 @@ pub struct NginxLog(pub Vec<NginxLogLine>);
 impl NginxLog {
     /// Creates a new NginxLog from a file at the given path
     ///
+    /// The format is detected from the first line of the file, see [`LogFormat::detect`].
+    ///
     /// # Arguments
     ///
     /// * `path` - The path to the Nginx log file
     ///
     /// # Errors
     ///
-    /// Returns an error if the file cannot be opened or read, or if the file is not valid JSON
+    /// Returns an error if the file cannot be opened or read, or if a line is not valid in the
+    /// detected format
     pub fn from_path<P>(path: P) -> Result<Self, Box<dyn std::error::Error>>
     where
         P: AsRef<std::path::Path>,
     {
-        let file = std::fs::File::open(path)?;
-        let reader = std::io::BufReader::new(file);
+        Self::from_path_with_format(path, None)
+    }
 
-        let mut log = Self(Vec::new());
+    /// Creates a new NginxLog from a file at the given path, written in a known format
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the Nginx log file
+    /// * `format` - The format of the file, or `None` to detect it from the first line
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened or read, or if a line is not valid in the
+    /// format
+    pub fn from_path_with_format<P>(
+        path: P,
+        format: Option<&LogFormat>,
+    ) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let reader = NginxLogReader::from_path(path, format.cloned())?;
+        Ok(Self(reader.collect::<Result<_, _>>()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    /// Returns the bytes of each line read, or the offset of each malformed line
+    fn outcomes(
+        lines: impl Iterator<Item = Result<NginxLogLine, ReadError>>,
+    ) -> Vec<Result<u64, u64>> {
+        lines
+            .map(|line| match line {
+                Ok(line) => Ok(line.bytes),
+                Err(ReadError::Parse { offset, .. }) => Err(offset),
+                Err(e) => panic!("{}", e),
+            })
+            .collect()
+    }
+
+    fn range(since: Option<u32>, until: Option<u32>) -> TimeRange {
+        let at = |minute| {
+            DateTime::parse_from_rfc3339(&format!("2026-10-10T13:{:02}:00+00:00", minute)).unwrap()
+        };
+        TimeRange::new(since.map(at), until.map(at))
+    }
+
+    #[test]
+    fn keeps_malformed_lines_only_inside_the_window() {
+        let minutes = [None, Some(1), None, Some(3), None, Some(4), Some(5), None];
+        let lines = minutes.map(|minute| match minute {
+            Some(minute) => format!(
+                "10.0.0.1 - - [10/Oct/2026:13:{:02}:00 +0000] \"GET / HTTP/1.1\" 200 {}\n",
+                minute, minute
+            ),
+            None => "malformed\n".to_owned(),
+        });
+        let offsets = lines
+            .iter()
+            .scan(0, |offset, line| {
+                *offset += line.len() as u64;
+                Some(*offset - line.len() as u64)
+            })
+            .collect::<Vec<_>>();
+        let log = lines.concat();
+
+        let dir = std::env::temp_dir().join(format!("nginx-parser-range-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("access.log"), &log).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(log.as_bytes()).unwrap();
+        std::fs::write(dir.join("access.log.gz"), encoder.finish().unwrap()).unwrap();
+
+        for (range, expected) in [
+            (range(Some(2), Some(5)), vec![Ok(3), Err(offsets[4]), Ok(4)]),
+            (
+                range(None, Some(2)),
+                vec![Err(offsets[0]), Ok(1), Err(offsets[2])],
+            ),
+            (range(Some(4), None), vec![Ok(4), Ok(5), Err(offsets[7])]),
+            (
+                range(None, None),
+                outcomes(NginxLogReader::new(Cursor::new(&log), None)),
+            ),
+        ] {
+            let streamed = NginxLogReader::new(Cursor::new(&log), Some(LogFormat::Combined));
+            assert_eq!(
+                outcomes(streamed.time_range(range)),
+                expected,
+                "{:?}",
+                range
+            );
+
+            for lines in 1..=3 {
+                let mut reader = NginxLogReader::new(Cursor::new(&log), Some(LogFormat::Combined))
+                    .time_range(range);
+                let mut batched = Vec::new();
+                while let Some(batch) = reader.next_batch(lines).unwrap() {
+                    batched.extend(outcomes(batch.parse()));
+                }
+                assert_eq!(batched, expected, "{:?} in batches of {}", range, lines);
+            }
 
-        for line in reader.lines() {
-            let line = line?;
-            let log_line: NginxLogLine = serde_json::from_str(&line)?;
-            log.0.push(log_line);
+            for file in ["access.log", "access.log.gz"] {
+                let reader =
+                    NginxLogReader::from_path_in_range(dir.join(file), None, range).unwrap();
+                assert_eq!(outcomes(reader), expected, "{:?} from {}", range, file);
+            }
         }
 
-        Ok(log)
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_invalid_utf8_as_malformed() {
+        let valid = "10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] \"GET / HTTP/1.1\" 200 1\n";
+        let invalid = b"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] \"GET /\xFF HTTP/1.1\" 200 2\n";
+        let log = [valid.as_bytes(), invalid, valid.as_bytes()].concat();
+        let expected = vec![Ok(1), Err(valid.len() as u64), Ok(1)];
+
+        let reader = NginxLogReader::new(Cursor::new(&log), None);
+        let mut report = ErrorReport::lenient(None);
+        let mut read = Vec::new();
+        for line in reader {
+            match line {
+                Ok(line) => read.push(Ok(line.bytes)),
+                Err(e) => {
+                    let ReadError::Parse { line, offset, .. } = &e else {
+                        panic!("{}", e);
+                    };
+                    assert_eq!(*line, Some(2));
+                    read.push(Err(*offset));
+                    report.record(None, e).unwrap();
+                }
+            }
+        }
+        assert_eq!(read, expected);
+        assert_eq!(report.count, 1);
+        assert!(report.examples[0].error.contains("utf-8"), "{:?}", report);
+
+        let mut reader = NginxLogReader::new(Cursor::new(&log), None);
+        let batch = reader.next_batch(3).unwrap().unwrap();
+        assert_eq!(outcomes(batch.parse()), expected);
+
+        // The time of the line is still read to skip it
+        let reader = NginxLogReader::new(Cursor::new(&log), None);
+        let reader = reader.time_range(range(Some(56), None));
+        assert_eq!(outcomes(reader), []);
     }
 }
@@ -0,0 +1,233 @@
+/// Normalization of endpoints into route templates
+use regex::Regex;
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::request::MALFORMED_ENDPOINT;
+
+/// A user supplied rule rewriting endpoints that match a regex into a template
+///
+/// Written as `PATTERN=TEMPLATE` on the command line, such as `^/files/.*=/files/:path`. The
+/// template can refer to capture groups with `${1}` or `${name}`. The braces can be left out
+/// when the group is not followed by a letter, digit or `_`: `$1_x` refers to a group named `1_x`,
+/// which does not exist and is replaced with nothing, so write `${1}_x`.
+#[derive(Debug, Clone)]
+pub struct RouteRule {
+    pattern: Regex,
+    template: String,
+}
+
+/// Error returned when a [`RouteRule`] cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidRouteRule {
+    pub rule: String,
+    pub message: String,
+}
+
+impl Display for InvalidRouteRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid route rule {:?}: {}", self.rule, self.message)
+    }
+}
+
+impl std::error::Error for InvalidRouteRule {}
+
+impl RouteRule {
+    /// Creates a new RouteRule
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The regex to match endpoints against
+    /// * `template` - What every match of the regex is replaced with
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the regex is invalid
+    pub fn new(pattern: &str, template: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: Regex::new(pattern)?,
+            template: template.to_owned(),
+        })
+    }
+}
+
+impl FromStr for RouteRule {
+    type Err = InvalidRouteRule;
+
+    /// Parses `PATTERN=TEMPLATE`, splitting at the last `=`
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let error = |message: String| InvalidRouteRule {
+            rule: rule.to_owned(),
+            message,
+        };
+
+        let (pattern, template) = rule
+            .rsplit_once('=')
+            .ok_or_else(|| error("expected PATTERN=TEMPLATE".to_owned()))?;
+        Self::new(pattern, template).map_err(|e| error(e.to_string()))
+    }
+}
+
+/// Rewrites endpoints into route templates, so that requests to the same route are counted
+/// together
+///
+/// User rules are applied first, in order, replacing every match of each. Then, if built-in
+/// rules are enabled, each path segment that is a number becomes `:id`, a UUID becomes `:uuid`
+/// and a hex string of at least 16 digits, like an MD5 or SHA hash, becomes `:hash`. For
+/// example `/users/8812/orders/77` becomes `/users/:id/orders/:id`.
+#[derive(Debug, Clone)]
+pub struct EndpointNormalizer {
+    builtin: bool,
+    rules: Vec<RouteRule>,
+}
+
+impl EndpointNormalizer {
+    /// Creates a new EndpointNormalizer
+    ///
+    /// # Arguments
+    ///
+    /// * `builtin` - Whether to collapse numeric IDs, UUIDs and hashes
+    /// * `rules` - User rules, applied in order before the built-in rules
+    pub fn new(builtin: bool, rules: Vec<RouteRule>) -> Self {
+        Self { builtin, rules }
+    }
+
+    /// Rewrites an endpoint into its route template
+    ///
+    /// [`MALFORMED_ENDPOINT`] is returned unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The decoded path of a request
+    pub fn normalize<'a>(&self, endpoint: &'a str) -> Cow<'a, str> {
+        if endpoint == MALFORMED_ENDPOINT {
+            return Cow::Borrowed(endpoint);
+        }
+
+        let mut endpoint = Cow::Borrowed(endpoint);
+        for rule in &self.rules {
+            if let Cow::Owned(rewritten) = rule.pattern.replace_all(&endpoint, &rule.template) {
+                endpoint = Cow::Owned(rewritten);
+            }
+        }
+
+        if self.builtin && endpoint.split('/').any(|s| placeholder(s).is_some()) {
+            let segments = endpoint
+                .split('/')
+                .map(|segment| placeholder(segment).unwrap_or(segment))
+                .collect::<Vec<_>>();
+            endpoint = Cow::Owned(segments.join("/"));
+        }
+
+        endpoint
+    }
+}
+
+/// Returns the placeholder a path segment is replaced with by the built-in rules, if any
+fn placeholder(segment: &str) -> Option<&'static str> {
+    let is_hex = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_hexdigit());
+
+    if !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()) {
+        Some(":id")
+    } else if segment.len() == 36
+        && segment.split('-').map(str::len).eq([8, 4, 4, 4, 12])
+        && segment.split('-').all(is_hex)
+    {
+        Some(":uuid")
+    } else if segment.len() >= 16 && is_hex(segment) {
+        Some(":hash")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(rules: &[&str], endpoint: &str) -> String {
+        let rules = rules.iter().map(|rule| rule.parse().unwrap()).collect();
+        EndpointNormalizer::new(true, rules)
+            .normalize(endpoint)
+            .into_owned()
+    }
+
+    #[test]
+    fn splits_rules_at_the_last_equals_sign() {
+        let rule: RouteRule = "^/search\\?q=.*=/search".parse().unwrap();
+        assert_eq!(rule.pattern.as_str(), "^/search\\?q=.*");
+        assert_eq!(rule.template, "/search");
+        assert_eq!(
+            normalize(&["^/search\\?q=.*=/search"], "/search?q=x"),
+            "/search"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let error = "^/files/.*".parse::<RouteRule>().unwrap_err();
+        assert_eq!(error.message, "expected PATTERN=TEMPLATE");
+
+        let error = "^/files/(.*=/files".parse::<RouteRule>().unwrap_err();
+        assert_eq!(error.rule, "^/files/(.*=/files");
+        assert!(error.message.contains("unclosed group"), "{}", error);
+    }
+
+    #[test]
+    fn expands_capture_groups_in_templates() {
+        assert_eq!(
+            normalize(&["^/(v[0-9]+)/.*=/$1/:rest"], "/v2/a/b"),
+            "/v2/:rest"
+        );
+        assert_eq!(
+            normalize(&["^/(?P<version>v[0-9]+)/.*=/${version}/:rest"], "/v2/a/b"),
+            "/v2/:rest"
+        );
+        assert_eq!(normalize(&["^/(v[0-9]+)/.*=/${1}_x"], "/v2/a"), "/v2_x");
+        // Without braces this is the group named `1_x`, which does not exist
+        assert_eq!(normalize(&["^/(v[0-9]+)/.*=/$1_x"], "/v2/a"), "/");
+    }
+
+    #[test]
+    fn applies_rules_in_order_before_the_builtin_rules() {
+        assert_eq!(
+            normalize(&["^/old/=/new/", "^/new/=/newer/"], "/old/1"),
+            "/newer/:id"
+        );
+    }
+
+    #[test]
+    fn collapses_ids_uuids_and_hashes() {
+        assert_eq!(
+            normalize(&[], "/users/8812/orders/77"),
+            "/users/:id/orders/:id"
+        );
+        assert_eq!(
+            normalize(&[], "/jobs/123e4567-e89b-12d3-a456-426614174000"),
+            "/jobs/:uuid"
+        );
+        assert_eq!(
+            normalize(&[], "/jobs/123E4567-E89B-12D3-A456-426614174000"),
+            "/jobs/:uuid"
+        );
+        assert_eq!(
+            normalize(&[], "/blobs/d41d8cd98f00b204e9800998ecf8427e"),
+            "/blobs/:hash"
+        );
+        assert_eq!(normalize(&[], "/blobs/0123456789abcdef"), "/blobs/:hash");
+        assert_eq!(
+            normalize(&[], "/blobs/0123456789abcde"),
+            "/blobs/0123456789abcde"
+        );
+        assert_eq!(normalize(&[], "/"), "/");
+    }
+
+    #[test]
+    fn leaves_malformed_requests_alone() {
+        assert_eq!(
+            normalize(&["^.*=/everything"], MALFORMED_ENDPOINT),
+            MALFORMED_ENDPOINT
+        );
+    }
+}
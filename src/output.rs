@@ -0,0 +1,435 @@
+/// Machine readable renderings of LogStats
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::nginx_log::ErrorReport;
+use crate::series::BucketStats;
+use crate::stats::{LatencyStats, LogStats, Percentile};
+
+/// Version of the JSON output schema, incremented whenever a field is changed or removed
+pub const JSON_SCHEMA_VERSION: u32 = 3;
+
+/// The JSON document printed by `--format json`
+///
+/// Alongside `schema_version`, every field of [`LogStats`] is included at the top level under the
+/// same name, with `null` for missing metrics and status codes as string keys. `files` holds the
+/// stats of each input file when they are requested, and `malformed_lines` the [`ErrorReport`].
+#[derive(Debug, Serialize)]
+pub struct JsonReport<'a> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub stats: &'a LogStats,
+    pub files: Vec<JsonFileStats<'a>>,
+    pub malformed_lines: &'a ErrorReport,
+}
+
+/// The stats of a single input file in a [`JsonReport`]
+#[derive(Debug, Serialize)]
+pub struct JsonFileStats<'a> {
+    pub path: &'a Path,
+    #[serde(flatten)]
+    pub stats: &'a LogStats,
+}
+
+impl<'a> JsonReport<'a> {
+    /// Creates a new JsonReport
+    ///
+    /// # Arguments
+    ///
+    /// * `stats` - The stats of all inputs combined
+    /// * `files` - The stats of each input file, if they should be included
+    /// * `malformed_lines` - The lines that could not be parsed
+    pub fn new(
+        stats: &'a LogStats,
+        files: &'a [(PathBuf, LogStats)],
+        malformed_lines: &'a ErrorReport,
+    ) -> Self {
+        Self {
+            schema_version: JSON_SCHEMA_VERSION,
+            stats,
+            files: files
+                .iter()
+                .map(|(path, stats)| JsonFileStats { path, stats })
+                .collect(),
+            malformed_lines,
+        }
+    }
+
+    /// Renders the report as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("LogStats is always serializable")
+    }
+
+    /// Renders the report as JSON on a single line, for streams of reports
+    pub fn to_json_line(&self) -> String {
+        serde_json::to_string(self).expect("LogStats is always serializable")
+    }
+}
+
+/// Renders time buckets as newline delimited JSON, one [`BucketStats`] object per line
+///
+/// # Arguments
+///
+/// * `buckets` - The buckets to render, usually [`LogStats::buckets`]
+pub fn to_ndjson(buckets: &[BucketStats]) -> String {
+    buckets
+        .iter()
+        .map(|bucket| {
+            let mut line = serde_json::to_string(bucket).expect("buckets are always serializable");
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+/// Options for [`PrometheusReport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrometheusOptions {
+    /// Prefix for every metric name, such as `nginx` for `nginx_requests_total`
+    pub prefix: String,
+    /// Labels added to every sample, such as the host the log came from. Must not include any of
+    /// [`RESERVED_LABELS`]
+    pub labels: Vec<(String, String)>,
+}
+
+impl Default for PrometheusOptions {
+    fn default() -> Self {
+        Self {
+            prefix: "nginx".to_owned(),
+            labels: Vec::new(),
+        }
+    }
+}
+
+/// Returns whether a string is a valid Prometheus metric name or prefix
+pub fn is_valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Labels set by [`PrometheusReport`] itself, which cannot be used as static labels
+pub const RESERVED_LABELS: &[&str] = &["status", "class", "quantile", "endpoint"];
+
+/// Returns whether a string is a valid Prometheus label name
+pub fn is_valid_label_name(name: &str) -> bool {
+    is_valid_metric_name(name) && !name.contains(':') && !name.starts_with("__")
+}
+
+/// Parses a static label given as `NAME=VALUE`
+///
+/// # Arguments
+///
+/// * `label` - The label to parse
+///
+/// # Errors
+///
+/// Returns an error if there is no `=`, or if the name is not a valid label name or is one of
+/// [`RESERVED_LABELS`]
+pub fn parse_label(label: &str) -> Result<(String, String), String> {
+    let (name, value) = label
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got {:?}", label))?;
+    if !is_valid_label_name(name) {
+        return Err(format!("{:?} is not a valid label name", name));
+    }
+    if RESERVED_LABELS.contains(&name) {
+        return Err(format!("{:?} is already used by the metrics", name));
+    }
+    Ok((name.to_owned(), value.to_owned()))
+}
+
+/// LogStats rendered in the Prometheus text exposition format
+///
+/// Suitable for node_exporter's textfile collector. The following metrics are written, each
+/// prefixed with [`PrometheusOptions::prefix`]:
+///
+/// * `_requests_total{status}` - Requests by status code
+/// * `_response_size_bytes{class, quantile}` - Summary of response sizes, where `class` is
+///   `all`, `successful` or `failed`
+/// * `_request_duration_seconds{class, quantile}` - Summary of `$request_time`, if the log has it
+/// * `_upstream_response_duration_seconds{class, quantile}` - Summary of
+///   `$upstream_response_time`, if the log has it
+/// * `_endpoint_requests_total{endpoint}` - Requests by endpoint
+/// * `_endpoint_failed_requests_total{endpoint}` - Failed requests by endpoint
+pub struct PrometheusReport<'a> {
+    stats: &'a LogStats,
+    options: &'a PrometheusOptions,
+}
+
+impl<'a> PrometheusReport<'a> {
+    /// Creates a new PrometheusReport
+    ///
+    /// # Arguments
+    ///
+    /// * `stats` - The stats to render
+    /// * `options` - The metric prefix and labels to use
+    pub fn new(stats: &'a LogStats, options: &'a PrometheusOptions) -> Self {
+        Self { stats, options }
+    }
+
+    /// Writes a sample with the static labels followed by the given labels
+    fn sample(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> std::fmt::Result {
+        write!(f, "{}_{}", self.options.prefix, name)?;
+
+        let static_labels = self
+            .options
+            .labels
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()));
+        for (i, (name, value)) in static_labels.chain(labels.iter().copied()).enumerate() {
+            let separator = if i == 0 { "{" } else { "," };
+            write!(f, "{}{}=\"{}\"", separator, name, escape_label_value(value))?;
+        }
+        if !self.options.labels.is_empty() || !labels.is_empty() {
+            write!(f, "}}")?;
+        }
+
+        writeln!(f, " {}", value)
+    }
+
+    fn header(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        name: &str,
+        kind: &str,
+        help: &str,
+    ) -> std::fmt::Result {
+        writeln!(f, "# HELP {}_{} {}", self.options.prefix, name, help)?;
+        writeln!(f, "# TYPE {}_{} {}", self.options.prefix, name, kind)
+    }
+
+    /// Writes a summary with a quantile sample for each percentile, by request class
+    fn summary(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        name: &str,
+        help: &str,
+        stats: &LatencyStats,
+    ) -> std::fmt::Result {
+        type Class = fn(&Percentile) -> Option<f64>;
+        let classes: [(_, _, _, Class); 3] = [
+            (
+                "all",
+                stats.count_all_requests,
+                stats.sum_all_requests,
+                |p| p.all_requests,
+            ),
+            (
+                "successful",
+                stats.count_successful_requests,
+                stats.sum_successful_requests,
+                |p| p.successful_requests,
+            ),
+            (
+                "failed",
+                stats.count_failed_requests,
+                stats.sum_failed_requests,
+                |p| p.failed_requests,
+            ),
+        ];
+
+        self.header(f, name, "summary", help)?;
+        for (class, count, sum, value) in classes {
+            for percentile in &stats.percentiles {
+                if let Some(value) = value(percentile) {
+                    let quantile = percentile.quantile().to_string();
+                    let labels = [("class", class), ("quantile", quantile.as_str())];
+                    self.sample(f, name, &labels, value)?;
+                }
+            }
+            self.sample(f, &format!("{}_sum", name), &[("class", class)], sum)?;
+            self.sample(
+                f,
+                &format!("{}_count", name),
+                &[("class", class)],
+                count as f64,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for PrometheusReport<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let stats = self.stats;
+
+        self.header(f, "requests_total", "counter", "Requests by status code.")?;
+        for (status, count) in &stats.status_count {
+            let status = status.to_string();
+            self.sample(f, "requests_total", &[("status", &status)], *count as f64)?;
+        }
+
+        let count_where = |predicate: fn(u16) -> bool| {
+            stats
+                .status_count
+                .iter()
+                .filter(|(status, _)| predicate(**status))
+                .map(|(_, count)| count)
+                .sum::<usize>()
+        };
+        let bytes = LatencyStats {
+            count_all_requests: count_where(|_| true),
+            count_successful_requests: count_where(|status| status < 400),
+            count_failed_requests: count_where(|status| status >= 400),
+            sum_all_requests: stats.sum_all_requests as f64,
+            sum_successful_requests: stats.sum_successful_requests as f64,
+            sum_failed_requests: stats.sum_failed_requests as f64,
+            mean_all_requests: stats.mean_all_requests,
+            mean_successful_requests: stats.mean_successful_requests,
+            mean_failed_requests: stats.mean_failed_requests,
+            percentiles: stats.percentiles.clone(),
+        };
+        self.summary(
+            f,
+            "response_size_bytes",
+            "Response body sizes by request class.",
+            &bytes,
+        )?;
+        if let Some(request_time) = &stats.request_time {
+            self.summary(
+                f,
+                "request_duration_seconds",
+                "Request processing times by request class.",
+                request_time,
+            )?;
+        }
+        if let Some(upstream_response_time) = &stats.upstream_response_time {
+            self.summary(
+                f,
+                "upstream_response_duration_seconds",
+                "Upstream response times by request class.",
+                upstream_response_time,
+            )?;
+        }
+
+        self.header(
+            f,
+            "endpoint_requests_total",
+            "counter",
+            "Requests by endpoint.",
+        )?;
+        for (endpoint, count) in &stats.endpoint_count {
+            let labels = [("endpoint", endpoint.as_str())];
+            self.sample(f, "endpoint_requests_total", &labels, *count as f64)?;
+        }
+
+        self.header(
+            f,
+            "endpoint_failed_requests_total",
+            "counter",
+            "Failed requests by endpoint.",
+        )?;
+        for (endpoint, count) in &stats.endpoint_failures {
+            let labels = [("endpoint", endpoint.as_str())];
+            self.sample(f, "endpoint_failed_requests_total", &labels, *count as f64)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Escapes a label value as required by the exposition format
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::LogStatsBuilder;
+
+    fn stats(lines: &[&str]) -> LogStats {
+        let mut builder = LogStatsBuilder::exact().percentiles(&[50.0]);
+        for line in lines {
+            builder.add(&crate::combined::parse_line(line).unwrap());
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn validates_labels() {
+        assert_eq!(
+            parse_label("host=web-1=a"),
+            Ok(("host".to_owned(), "web-1=a".to_owned()))
+        );
+        assert_eq!(parse_label("job="), Ok(("job".to_owned(), String::new())));
+        for invalid in ["host", "1host=a", "__host=a", "a:b=c", "=a"] {
+            assert!(parse_label(invalid).is_err(), "{}", invalid);
+        }
+        for reserved in RESERVED_LABELS {
+            let error = parse_label(&format!("{}=a", reserved)).unwrap_err();
+            assert!(error.ends_with("is already used by the metrics"));
+        }
+
+        assert!(is_valid_metric_name("nginx:access_2"));
+        assert!(!is_valid_metric_name("2nginx"));
+        assert!(!is_valid_metric_name(""));
+        assert_eq!(escape_label_value("a\\b\"c\nd"), r#"a\\b\"c\nd"#);
+    }
+
+    #[test]
+    fn renders_the_exposition_format() {
+        let stats = stats(&[
+            r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "GET /a HTTP/1.1" 200 1 "-" "-""#,
+            r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "GET /a HTTP/1.1" 200 2 "-" "-""#,
+            r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "GET /\x22b\x5C HTTP/1.1" 404 4 "-" "-""#,
+        ]);
+        let options = PrometheusOptions {
+            prefix: "web".to_owned(),
+            labels: vec![("host".to_owned(), "a\"b".to_owned())],
+        };
+
+        let expected = r#"# HELP web_requests_total Requests by status code.
+# TYPE web_requests_total counter
+web_requests_total{host="a\"b",status="200"} 2
+web_requests_total{host="a\"b",status="404"} 1
+# HELP web_response_size_bytes Response body sizes by request class.
+# TYPE web_response_size_bytes summary
+web_response_size_bytes{host="a\"b",class="all",quantile="0.5"} 2
+web_response_size_bytes_sum{host="a\"b",class="all"} 7
+web_response_size_bytes_count{host="a\"b",class="all"} 3
+web_response_size_bytes{host="a\"b",class="successful",quantile="0.5"} 2
+web_response_size_bytes_sum{host="a\"b",class="successful"} 3
+web_response_size_bytes_count{host="a\"b",class="successful"} 2
+web_response_size_bytes{host="a\"b",class="failed",quantile="0.5"} 4
+web_response_size_bytes_sum{host="a\"b",class="failed"} 4
+web_response_size_bytes_count{host="a\"b",class="failed"} 1
+# HELP web_endpoint_requests_total Requests by endpoint.
+# TYPE web_endpoint_requests_total counter
+web_endpoint_requests_total{host="a\"b",endpoint="/\"b\\"} 1
+web_endpoint_requests_total{host="a\"b",endpoint="/a"} 2
+# HELP web_endpoint_failed_requests_total Failed requests by endpoint.
+# TYPE web_endpoint_failed_requests_total counter
+web_endpoint_failed_requests_total{host="a\"b",endpoint="/\"b\\"} 1
+"#;
+        assert_eq!(
+            PrometheusReport::new(&stats, &options).to_string(),
+            expected
+        );
+    }
+
+    #[test]
+    fn sums_are_exact() {
+        let stats = stats(&[
+            r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "GET / HTTP/1.1" 200 9007199254740993"#,
+            r#"10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "GET / HTTP/1.1" 200 1"#,
+        ]);
+        assert_eq!(stats.sum_all_requests, 9007199254740994);
+
+        let text = PrometheusReport::new(&stats, &PrometheusOptions::default()).to_string();
+        assert!(text.contains("nginx_response_size_bytes_sum{class=\"all\"} 9007199254740994\n"));
+    }
+}
@@ -0,0 +1,210 @@
+/// Parsing and aggregation of log lines across threads
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::{mpsc, Mutex};
+
+use crate::nginx_log::{ErrorReport, LineBatch, NginxLogReader, ReadError};
+use crate::stats::LogStatsBuilder;
+
+/// The number of lines handed to a thread at a time
+pub const BATCH_LINES: usize = 4096;
+
+/// The stats and errors from parsing one batch of lines
+type BatchResult = (usize, LogStatsBuilder, Vec<ReadError>);
+
+/// Adds every line from a reader to the stats, parsing lines on several threads
+///
+/// Lines are read on the calling thread in batches of [`BATCH_LINES`], which are parsed and
+/// aggregated by `threads` worker threads. Each batch is merged back in order, and its malformed
+/// lines recorded in order, so the result is identical to adding each line from the reader to
+/// `builder` and recording each error in `report` on a single thread, apart from approximate
+/// top client counts, see [`LogStatsBuilder::merge`].
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read lines from
+/// * `threads` - The number of threads to parse lines on
+/// * `builder` - The builder to add lines to
+/// * `report` - The report to record malformed lines in
+/// * `file` - The file being read, if known, for the report
+///
+/// # Errors
+///
+/// Returns an error if the reader fails, or if `report` does not allow a malformed line
+pub fn aggregate<R: BufRead>(
+    reader: &mut NginxLogReader<R>,
+    threads: NonZeroUsize,
+    builder: &mut LogStatsBuilder,
+    report: &mut ErrorReport,
+    file: Option<&Path>,
+) -> Result<(), ReadError> {
+    let threads = threads.get();
+    let template = builder.new_like();
+
+    let (job_sender, job_receiver) = mpsc::sync_channel::<(usize, LineBatch)>(threads * 2);
+    let job_receiver = Mutex::new(job_receiver);
+    let (result_sender, result_receiver) = mpsc::channel::<BatchResult>();
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            let job_receiver = &job_receiver;
+            let result_sender = result_sender.clone();
+            let template = &template;
+            scope.spawn(move || loop {
+                let job = job_receiver.lock().unwrap().recv();
+                let Ok((index, batch)) = job else {
+                    break;
+                };
+
+                let mut builder = template.new_like();
+                let mut errors = Vec::new();
+                for line in batch.parse() {
+                    match line {
+                        Ok(line) => builder.add(&line),
+                        Err(e) => errors.push(e),
+                    }
+                }
+
+                if result_sender.send((index, builder, errors)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_sender);
+
+        let mut pending = BTreeMap::new();
+        let mut merged = 0;
+        let mut merge_ready = |pending: &mut BTreeMap<usize, (LogStatsBuilder, Vec<ReadError>)>| {
+            while let Some((batch, errors)) = pending.remove(&merged) {
+                builder.merge(batch);
+                for error in errors {
+                    report.record(file, error)?;
+                }
+                merged += 1;
+            }
+            Ok::<_, ReadError>(())
+        };
+
+        let mut sent = 0;
+        while let Some(batch) = reader.next_batch(BATCH_LINES)? {
+            for (index, batch, errors) in result_receiver.try_iter() {
+                pending.insert(index, (batch, errors));
+            }
+            merge_ready(&mut pending)?;
+
+            job_sender
+                .send((sent, batch))
+                .expect("parsing threads stop only once jobs are done");
+            sent += 1;
+        }
+        drop(job_sender);
+
+        for (index, batch, errors) in result_receiver {
+            pending.insert(index, (batch, errors));
+            merge_ready(&mut pending)?;
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::LogStats;
+    use std::io::Cursor;
+
+    /// A combined log over several batches, with a malformed line every 997 lines
+    fn log() -> String {
+        (0..BATCH_LINES * 3 + 123)
+            .map(|i| {
+                if i % 997 == 5 {
+                    return format!("malformed line {}\n", i);
+                }
+                format!(
+                    "10.0.{}.{} - - [10/Oct/2026:13:{:02}:00 +0000] \"GET /p/{} HTTP/1.1\" {} {}\n",
+                    i % 7,
+                    i % 11,
+                    i % 60,
+                    i % 13,
+                    if i % 17 == 0 { 502 } else { 200 },
+                    i * 31 % 1000
+                )
+            })
+            .collect()
+    }
+
+    fn builder() -> LogStatsBuilder {
+        LogStatsBuilder::new().per_endpoint()
+    }
+
+    fn report(max_errors: Option<usize>) -> ErrorReport {
+        ErrorReport::lenient(max_errors).with_max_examples(100)
+    }
+
+    /// Reads the log on the calling thread, returning the stats, report and final error
+    fn sequential(max_errors: Option<usize>) -> (LogStats, ErrorReport, Option<String>) {
+        let mut reader = NginxLogReader::new(Cursor::new(log()), None);
+        let mut builder = builder();
+        let mut report = report(max_errors);
+        let mut error = None;
+        for line in &mut reader {
+            match line {
+                Ok(line) => builder.add(&line),
+                Err(e) => {
+                    if let Err(e) = report.record(None, e) {
+                        error = Some(e.to_string());
+                        break;
+                    }
+                }
+            }
+        }
+        (builder.build(), report, error)
+    }
+
+    fn parallel(
+        threads: usize,
+        max_errors: Option<usize>,
+    ) -> (LogStats, ErrorReport, Option<String>) {
+        let mut reader = NginxLogReader::new(Cursor::new(log()), None);
+        let mut builder = builder();
+        let mut report = report(max_errors);
+        let threads = NonZeroUsize::new(threads).unwrap();
+        let result = aggregate(&mut reader, threads, &mut builder, &mut report, None);
+        (builder.build(), report, result.err().map(|e| e.to_string()))
+    }
+
+    #[test]
+    fn matches_a_single_thread() {
+        let (stats, report, error) = sequential(None);
+        assert_eq!(report.count, 13);
+        assert!(report.examples.is_sorted_by_key(|example| example.line));
+        assert_eq!(error, None);
+
+        for threads in [1, 2, 3, 8] {
+            let (parallel_stats, parallel_report, parallel_error) = parallel(threads, None);
+            assert_eq!(parallel_stats, stats, "{} threads", threads);
+            assert_eq!(parallel_report, report, "{} threads", threads);
+            assert_eq!(parallel_error, None);
+        }
+    }
+
+    #[test]
+    fn stops_at_the_same_malformed_line() {
+        let (_, report, error) = sequential(Some(4));
+        assert_eq!(report.count, 5);
+        assert_eq!(report.examples.len(), 4);
+        assert_eq!(
+            error.as_deref().map(|e| e.split(':').next()),
+            Some(Some("line 3994"))
+        );
+
+        for threads in [1, 2, 3, 8] {
+            let (_, parallel_report, parallel_error) = parallel(threads, Some(4));
+            assert_eq!(parallel_report, report, "{} threads", threads);
+            assert_eq!(parallel_error, error, "{} threads", threads);
+        }
+    }
+}
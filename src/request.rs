@@ -0,0 +1,348 @@
+/// Parsing of HTTP request lines as logged in nginx's `$request`
+use std::fmt::Display;
+
+/// Endpoint used in statistics for requests whose request line could not be parsed
+pub const MALFORMED_ENDPOINT: &str = "<malformed>";
+
+/// An HTTP request method
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    /// Any other method, such as the WebDAV extensions
+    Other(String),
+}
+
+impl Method {
+    /// Returns the method as it appears in a request line
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Get => "GET",
+            Self::Head => "HEAD",
+            Self::Post => "POST",
+            Self::Put => "PUT",
+            Self::Delete => "DELETE",
+            Self::Connect => "CONNECT",
+            Self::Options => "OPTIONS",
+            Self::Trace => "TRACE",
+            Self::Patch => "PATCH",
+            Self::Other(method) => method,
+        }
+    }
+
+    fn parse(method: &str) -> Option<Self> {
+        let valid = !method.is_empty()
+            && method
+                .bytes()
+                .all(|b| b.is_ascii_uppercase() || b == b'_' || b == b'-');
+        if !valid {
+            return None;
+        }
+
+        Some(match method {
+            "GET" => Self::Get,
+            "HEAD" => Self::Head,
+            "POST" => Self::Post,
+            "PUT" => Self::Put,
+            "DELETE" => Self::Delete,
+            "CONNECT" => Self::Connect,
+            "OPTIONS" => Self::Options,
+            "TRACE" => Self::Trace,
+            "PATCH" => Self::Patch,
+            other => Self::Other(other.to_owned()),
+        })
+    }
+}
+
+impl Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The HTTP version of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HttpVersion {
+    /// A request line with no version
+    Http09,
+    Http10,
+    Http11,
+    Http2,
+    Http3,
+}
+
+impl HttpVersion {
+    fn parse(version: &str) -> Option<Self> {
+        match version {
+            "HTTP/1.0" => Some(Self::Http10),
+            "HTTP/1.1" => Some(Self::Http11),
+            "HTTP/2.0" | "HTTP/2" => Some(Self::Http2),
+            "HTTP/3.0" | "HTTP/3" => Some(Self::Http3),
+            _ => None,
+        }
+    }
+}
+
+impl Display for HttpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Http09 => "HTTP/0.9",
+            Self::Http10 => "HTTP/1.0",
+            Self::Http11 => "HTTP/1.1",
+            Self::Http2 => "HTTP/2.0",
+            Self::Http3 => "HTTP/3.0",
+        })
+    }
+}
+
+/// A parsed HTTP request line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: Method,
+    /// The percent-decoded path, without the query string
+    ///
+    /// For absolute-form targets such as `http://example.com/a` this is only the path, and for
+    /// `CONNECT` requests it is the `host:port` authority.
+    pub path: String,
+    /// The percent-decoded query parameters, in order
+    pub query: Vec<(String, String)>,
+    pub version: HttpVersion,
+}
+
+/// Error returned for request lines that are not valid HTTP
+///
+/// nginx logs whatever the client sent, so these are common from scanners and from TLS
+/// handshakes sent to plain HTTP ports, which nginx logs as `\x16\x03\x01`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MalformedRequest {
+    /// The request line that could not be parsed
+    pub raw: String,
+    pub reason: &'static str,
+}
+
+impl Display for MalformedRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "malformed request {:?}: {}", self.raw, self.reason)
+    }
+}
+
+impl std::error::Error for MalformedRequest {}
+
+impl Request {
+    /// Parses a request line such as `GET /index.html?page=2 HTTP/1.1`
+    ///
+    /// # Arguments
+    ///
+    /// * `raw` - The request line of nginx's `$request`, with the `\xHH` escapes of text logs
+    ///   already decoded as [`crate::combined::unescape`] does when lines are parsed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line does not have a valid method, target and HTTP version, or if
+    /// it contains control characters such as the bytes of a TLS handshake
+    pub fn parse(raw: &str) -> Result<Self, MalformedRequest> {
+        let malformed = |reason| MalformedRequest {
+            raw: raw.to_owned(),
+            reason,
+        };
+
+        if raw.chars().any(char::is_control) {
+            return Err(malformed("contains binary data"));
+        }
+
+        let mut parts = raw.split(' ');
+        let method = parts
+            .next()
+            .and_then(Method::parse)
+            .ok_or_else(|| malformed("invalid method"))?;
+        let target = parts
+            .next()
+            .filter(|target| !target.is_empty())
+            .ok_or_else(|| malformed("missing target"))?;
+        let version = match parts.next() {
+            Some(version) => {
+                HttpVersion::parse(version).ok_or_else(|| malformed("invalid HTTP version"))?
+            }
+            None => HttpVersion::Http09,
+        };
+        if parts.next().is_some() {
+            return Err(malformed("unexpected text after HTTP version"));
+        }
+
+        let (path, query) = if method == Method::Connect {
+            (target, "")
+        } else if target.starts_with('/') || target == "*" {
+            target.split_once('?').unwrap_or((target, ""))
+        } else if let Some((_, rest)) = target.split_once("://") {
+            let target = rest.find('/').map(|i| &rest[i..]).unwrap_or("/");
+            target.split_once('?').unwrap_or((target, ""))
+        } else {
+            return Err(malformed("invalid target"));
+        };
+
+        Ok(Self {
+            method,
+            path: percent_decode(path, false),
+            query: parse_query(query),
+            version,
+        })
+    }
+
+    /// Returns the value of the first query parameter with the given name
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Splits a query string into decoded key-value pairs
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key, true), percent_decode(value, true))
+        })
+        .collect()
+}
+
+/// Decodes `%HH` escapes, and `+` as a space if `plus_as_space` is set
+///
+/// Invalid escapes are kept as written and invalid UTF-8 is replaced.
+fn percent_decode(value: &str, plus_as_space: bool) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len()
+                && bytes[i + 1].is_ascii_hexdigit()
+                && bytes[i + 2].is_ascii_hexdigit() =>
+            {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap_or_default());
+                i += 3;
+                continue;
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Request {
+        Request::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn rejects_tls_handshakes_and_garbage() {
+        for raw in [
+            r"\x16\x03\x01\x02\x00\x01\x00\x01\xFC\x03\x03",
+            "\u{16}\u{3}\u{1}",
+            "-",
+            "",
+            "get / HTTP/1.1",
+            "GET",
+            "GET / HTTP/1.1 extra",
+            "GET / HTTP/4.2",
+            "GET index.html HTTP/1.1",
+        ] {
+            let error = Request::parse(raw).unwrap_err();
+            assert_eq!(error.raw, raw);
+        }
+    }
+
+    #[test]
+    fn escapes_are_decoded_once() {
+        // The line parsers decode the escapes of text logs, so a request line holds them as text
+        assert_eq!(parse(r"GET /a\x41 HTTP/1.1").path, r"/a\x41");
+
+        let logged = |request: &str| {
+            let line = format!(
+                r#"::1 - - [10/Oct/2026:13:55:36 +0000] "{}" 200 0"#,
+                request
+            );
+            crate::combined::parse_line(&line).unwrap()
+        };
+        let line = logged(r"GET /a\x5Cx41 HTTP/1.1");
+        assert_eq!(line.request, r"GET /a\x41 HTTP/1.1");
+        assert_eq!(line.request_line().unwrap().path, r"/a\x41");
+
+        let request = logged(r"GET /caf\xC3\xA9?q=\x22a\x22 HTTP/1.1").request_line();
+        let request = request.unwrap();
+        assert_eq!(request.path, "/café");
+        assert_eq!(request.query_param("q"), Some("\"a\""));
+
+        let error = logged(r"\x16\x03\x01").request_line().unwrap_err();
+        assert_eq!(error.reason, "contains binary data");
+    }
+
+    #[test]
+    fn parses_every_target_form() {
+        let request = parse("GET http://example.com:8080/a/b?x=1 HTTP/1.1");
+        assert_eq!(request.path, "/a/b");
+        assert_eq!(request.query_param("x"), Some("1"));
+        assert_eq!(parse("GET https://example.com HTTP/1.1").path, "/");
+
+        let request = parse("CONNECT example.com:443 HTTP/1.1");
+        assert_eq!(request.method, Method::Connect);
+        assert_eq!(request.path, "example.com:443");
+        assert!(request.query.is_empty());
+
+        let request = parse("OPTIONS * HTTP/1.1");
+        assert_eq!(
+            (request.method, request.path.as_str()),
+            (Method::Options, "*")
+        );
+        let request = parse("PROPFIND /dav/ HTTP/1.1");
+        assert_eq!(request.method, Method::Other("PROPFIND".to_owned()));
+    }
+
+    #[test]
+    fn decodes_paths_and_queries() {
+        let request =
+            parse("GET /a%20b+c/%E2%9C%93?a=1&b=hello+world&c=%C3%A9&d&&e=%ZZ&a=2 HTTP/2.0");
+        assert_eq!(request.path, "/a b+c/✓");
+        assert_eq!(
+            request.query,
+            [
+                ("a", "1"),
+                ("b", "hello world"),
+                ("c", "é"),
+                ("d", ""),
+                ("e", "%ZZ"),
+                ("a", "2")
+            ]
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        );
+        assert_eq!(request.query_param("a"), Some("1"));
+        assert_eq!(request.query_param("missing"), None);
+        assert_eq!(request.version, HttpVersion::Http2);
+    }
+
+    #[test]
+    fn parses_http_0_9_requests() {
+        let request = parse("GET /index.html");
+        assert_eq!(request.version, HttpVersion::Http09);
+        assert_eq!(request.path, "/index.html");
+        assert_eq!(request.version.to_string(), "HTTP/0.9");
+    }
+}
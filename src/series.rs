@@ -0,0 +1,229 @@
+/// Time series of LogStats, aggregated per fixed-width time bucket
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::fmt::Display;
+use std::num::NonZeroU64;
+use std::str::FromStr;
+
+use crate::stats::RequestStats;
+use crate::table;
+
+/// The width of the time buckets of a series, a whole number of seconds
+///
+/// Parsed from a number followed by `s`, `m`, `h` or `d`, such as `1m`, `5m` or `1h`. Buckets
+/// are aligned to multiples of their width since the Unix epoch, so `1h` buckets start on the
+/// hour and `1d` buckets at midnight UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketWidth(NonZeroU64);
+
+/// Error returned when a [`BucketWidth`] cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidBucketWidth {
+    pub value: String,
+}
+
+impl Display for InvalidBucketWidth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid bucket width {:?}, expected a number followed by s, m, h or d such as 5m",
+            self.value
+        )
+    }
+}
+
+impl std::error::Error for InvalidBucketWidth {}
+
+/// Units a [`BucketWidth`] can be written in, largest first
+const UNITS: [(char, u64); 4] = [('d', 86400), ('h', 3600), ('m', 60), ('s', 1)];
+
+impl BucketWidth {
+    /// Creates a new BucketWidth
+    ///
+    /// # Arguments
+    ///
+    /// * `seconds` - The width of each bucket in seconds
+    pub fn from_seconds(seconds: NonZeroU64) -> Self {
+        Self(seconds)
+    }
+
+    /// Returns the width of each bucket in seconds
+    pub fn seconds(&self) -> u64 {
+        self.0.get()
+    }
+
+    /// Returns the start of the bucket a time falls in, as seconds since the Unix epoch
+    pub fn bucket(&self, time: &DateTime<FixedOffset>) -> i64 {
+        // Wider buckets than i64::MAX seconds hold every representable time anyway
+        let width = i64::try_from(self.seconds()).unwrap_or(i64::MAX);
+        time.timestamp().div_euclid(width) * width
+    }
+}
+
+impl FromStr for BucketWidth {
+    type Err = InvalidBucketWidth;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidBucketWidth {
+            value: value.to_owned(),
+        };
+
+        let unit = value.chars().last().ok_or_else(error)?;
+        let (_, seconds) = UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .ok_or_else(error)?;
+        let count = value[..value.len() - 1]
+            .parse::<u64>()
+            .map_err(|_| error())?;
+        count
+            .checked_mul(*seconds)
+            .and_then(NonZeroU64::new)
+            .map(Self)
+            .ok_or_else(error)
+    }
+}
+
+impl Display for BucketWidth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let seconds = self.seconds();
+        let (unit, size) = UNITS
+            .iter()
+            .find(|(_, size)| seconds.is_multiple_of(*size))
+            .expect("every width is a whole number of seconds");
+        write!(f, "{}{}", seconds / size, unit)
+    }
+}
+
+/// Statistics about the requests logged in one time bucket
+///
+/// # Contains
+///
+/// * `start` - The start of the bucket, in the time zone of the log
+/// * `end` - The end of the bucket, exclusive
+/// * `requests_per_second` - The mean number of requests per second over the bucket
+/// * `stats` - Statistics about the requests in the bucket, flattened into the same JSON object
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BucketStats {
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+    pub requests_per_second: f64,
+    #[serde(flatten)]
+    pub stats: RequestStats,
+}
+
+/// A text table of time buckets, one row per bucket in the given order
+pub struct BucketTable<'a>(pub &'a [BucketStats]);
+
+impl Display for BucketTable<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let columns = table::Columns::of(self.0.iter().map(|bucket| &bucket.stats));
+
+        let mut header = vec!["Start".to_owned(), "Req/s".to_owned()];
+        header.extend(columns.header());
+        let mut rows = vec![header];
+        for bucket in self.0 {
+            let mut row = vec![
+                bucket.start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                format!("{:.2}", bucket.requests_per_second),
+            ];
+            row.extend(columns.cells(&bucket.stats));
+            rows.push(row);
+        }
+
+        table::write(f, &rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn width(seconds: u64) -> BucketWidth {
+        BucketWidth::from_seconds(NonZeroU64::new(seconds).unwrap())
+    }
+
+    fn time(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    #[test]
+    fn parses_and_displays_widths() {
+        for (value, seconds, displayed) in [
+            ("30s", 30, "30s"),
+            ("1m", 60, "1m"),
+            ("90s", 90, "90s"),
+            ("120s", 120, "2m"),
+            ("5m", 300, "5m"),
+            ("60m", 3600, "1h"),
+            ("1h", 3600, "1h"),
+            ("1d", 86400, "1d"),
+            ("48h", 172800, "2d"),
+        ] {
+            let width = value.parse::<BucketWidth>().unwrap();
+            assert_eq!(width.seconds(), seconds, "{}", value);
+            assert_eq!(width.to_string(), displayed);
+            assert_eq!(displayed.parse(), Ok(width));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_widths() {
+        for value in [
+            "", "0m", "0s", "m", "5", "5w", "-5m", "1.5h", " 5m", "5 m", "5é",
+        ] {
+            assert_eq!(
+                value.parse::<BucketWidth>(),
+                Err(InvalidBucketWidth {
+                    value: value.to_owned()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_widths_that_overflow() {
+        let days = u64::MAX / 86400;
+        assert_eq!(
+            format!("{}d", days)
+                .parse::<BucketWidth>()
+                .unwrap()
+                .seconds(),
+            days * 86400
+        );
+        assert!(format!("{}d", days + 1).parse::<BucketWidth>().is_err());
+        assert!("18446744073709551616s".parse::<BucketWidth>().is_err());
+    }
+
+    #[test]
+    fn aligns_buckets_to_the_epoch() {
+        let five_minutes = width(300);
+        assert_eq!(five_minutes.bucket(&time("1970-01-01T00:00:00Z")), 0);
+        assert_eq!(five_minutes.bucket(&time("1970-01-01T00:04:59Z")), 0);
+        assert_eq!(five_minutes.bucket(&time("1970-01-01T00:05:00Z")), 300);
+        assert_eq!(
+            width(3600).bucket(&time("2024-03-01T12:34:56+02:00")),
+            time("2024-03-01T10:00:00Z").timestamp()
+        );
+    }
+
+    #[test]
+    fn aligns_buckets_before_1970() {
+        let five_minutes = width(300);
+        assert_eq!(five_minutes.bucket(&time("1969-12-31T23:59:59Z")), -300);
+        assert_eq!(five_minutes.bucket(&time("1969-12-31T23:55:00Z")), -300);
+        assert_eq!(five_minutes.bucket(&time("1969-12-31T23:54:59Z")), -600);
+        assert_eq!(
+            width(86400).bucket(&time("1900-06-15T12:00:00Z")),
+            time("1900-06-15T00:00:00Z").timestamp()
+        );
+    }
+
+    #[test]
+    fn puts_everything_in_one_bucket_when_wider_than_i64() {
+        let huge = width(u64::MAX);
+        assert_eq!(huge.bucket(&time("2024-03-01T12:34:56Z")), 0);
+        assert_eq!(huge.bucket(&time("1970-01-01T00:00:00Z")), 0);
+        assert_eq!(huge.bucket(&time("1969-12-31T23:59:59Z")), -i64::MAX);
+    }
+}
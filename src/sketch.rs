@@ -0,0 +1,249 @@
+/// A mergeable quantile sketch with a relative accuracy guarantee
+use std::collections::BTreeMap;
+
+/// A DDSketch over non-negative integers
+///
+/// Values are counted in logarithmically sized buckets, so any quantile is estimated to within
+/// `relative_accuracy` of the true value, using memory that grows with the logarithm of the range
+/// of values rather than their count. At the default accuracy the whole range of `u64` fits in
+/// about 2,200 buckets. Sketches with the same accuracy can be merged, giving the same sketch as
+/// if all values had been added to one.
+///
+/// See Masson et al., "DDSketch: A Fast and Fully-Mergeable Quantile Sketch with Relative-Error
+/// Guarantees", VLDB 2019.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DDSketch {
+    relative_accuracy: f64,
+    ln_gamma: f64,
+    zero_count: u64,
+    bins: BTreeMap<i32, u64>,
+    count: u64,
+    min: u64,
+    max: u64,
+}
+
+impl DDSketch {
+    /// The relative accuracy used when none is given
+    pub const DEFAULT_RELATIVE_ACCURACY: f64 = 0.01;
+
+    /// Creates a new empty DDSketch
+    ///
+    /// # Arguments
+    ///
+    /// * `relative_accuracy` - The maximum relative error of quantile estimates, between 0 and 1
+    ///
+    /// # Panics
+    ///
+    /// Panics if `relative_accuracy` is not strictly between 0 and 1
+    pub fn new(relative_accuracy: f64) -> Self {
+        assert!(
+            relative_accuracy > 0.0 && relative_accuracy < 1.0,
+            "relative accuracy must be between 0 and 1"
+        );
+
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        Self {
+            relative_accuracy,
+            ln_gamma: gamma.ln(),
+            zero_count: 0,
+            bins: BTreeMap::new(),
+            count: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Returns the maximum relative error of quantile estimates
+    pub fn relative_accuracy(&self) -> f64 {
+        self.relative_accuracy
+    }
+
+    /// Returns the number of values added
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns whether no values have been added
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn index(&self, value: u64) -> i32 {
+        ((value as f64).ln() / self.ln_gamma).ceil() as i32
+    }
+
+    /// Returns the value in the middle of a bin, which is within the relative accuracy of every
+    /// value counted in it
+    fn value(&self, index: i32) -> f64 {
+        let gamma = self.ln_gamma.exp();
+        2.0 * (index as f64 * self.ln_gamma).exp() / (gamma + 1.0)
+    }
+
+    /// Adds a value to the sketch
+    pub fn add(&mut self, value: u64) {
+        if value == 0 {
+            self.zero_count += 1;
+        } else {
+            *self.bins.entry(self.index(value)).or_insert(0) += 1;
+        }
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Adds all values from another sketch to this one
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sketches have different relative accuracies
+    pub fn merge(&mut self, other: &DDSketch) {
+        assert!(
+            self.relative_accuracy == other.relative_accuracy,
+            "cannot merge sketches with different relative accuracies"
+        );
+
+        self.zero_count += other.zero_count;
+        for (index, count) in &other.bins {
+            *self.bins.entry(*index).or_insert(0) += count;
+        }
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Estimates the value at a quantile, or `None` if the sketch is empty
+    ///
+    /// Estimates the value at zero-based index `floor(count * quantile)` of the sorted values,
+    /// capped at the last one, as [`Percentile`](crate::stats::Percentile) does. Estimates are
+    /// within the relative accuracy of that exact value, and the smallest and largest values are
+    /// returned exactly.
+    ///
+    /// # Arguments
+    ///
+    /// * `quantile` - The quantile to estimate, between 0 and 1
+    pub fn quantile(&self, quantile: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((self.count as f64 * quantile) as u64).min(self.count - 1);
+        if rank == 0 {
+            return Some(self.min as f64);
+        } else if rank == self.count - 1 {
+            return Some(self.max as f64);
+        } else if rank < self.zero_count {
+            return Some(0.0);
+        }
+
+        let mut seen = self.zero_count;
+        for (index, count) in &self.bins {
+            seen += count;
+            if seen > rank {
+                let value = self.value(*index);
+                return Some(value.clamp(self.min as f64, self.max as f64));
+            }
+        }
+
+        Some(self.max as f64)
+    }
+}
+
+impl Default for DDSketch {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_RELATIVE_ACCURACY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sketch(relative_accuracy: f64, values: &[u64]) -> DDSketch {
+        let mut sketch = DDSketch::new(relative_accuracy);
+        values.iter().for_each(|value| sketch.add(*value));
+        sketch
+    }
+
+    #[test]
+    fn counts_zeros() {
+        let zeros = sketch(0.01, &[0; 10]);
+        assert_eq!(zeros.count(), 10);
+        for quantile in [0.0, 0.5, 1.0] {
+            assert_eq!(zeros.quantile(quantile), Some(0.0));
+        }
+
+        let mostly_zeros = sketch(0.01, &[0, 0, 0, 1000]);
+        assert_eq!(mostly_zeros.quantile(0.5), Some(0.0));
+        assert_eq!(mostly_zeros.quantile(1.0), Some(1000.0));
+    }
+
+    #[test]
+    fn returns_the_smallest_and_largest_values_exactly() {
+        let empty = DDSketch::default();
+        assert!(empty.is_empty());
+        assert_eq!(empty.quantile(0.5), None);
+
+        let values = (997..=100_003).collect::<Vec<_>>();
+        let sketch = sketch(0.05, &values);
+        assert_eq!(sketch.quantile(0.0), Some(997.0));
+        assert_eq!(sketch.quantile(1.0), Some(100_003.0));
+
+        let single = self::sketch(0.01, &[u64::MAX]);
+        assert_eq!(single.quantile(0.5), Some(u64::MAX as f64));
+    }
+
+    #[test]
+    fn estimates_are_within_the_relative_accuracy() {
+        // Values from 1 to about 10^19, some spread out and some repeated
+        let mut values = (0..140u32)
+            .map(|k| 1.37f64.powi(k as i32) as u64)
+            .chain((1..=2000).map(|i| i * 7919))
+            .chain([1; 50])
+            .collect::<Vec<_>>();
+        values.sort();
+
+        for relative_accuracy in [0.001, 0.01, 0.05] {
+            let sketch = sketch(relative_accuracy, &values);
+            assert_eq!(sketch.count(), values.len() as u64);
+            for percentile in 0..=1000 {
+                let quantile = percentile as f64 / 1000.0;
+                let index = ((values.len() as f64 * quantile) as usize).min(values.len() - 1);
+                let exact = values[index] as f64;
+                let estimate = sketch.quantile(quantile).unwrap();
+                assert!(
+                    (estimate - exact).abs() <= exact * relative_accuracy * (1.0 + 1e-9),
+                    "q{} at {}: {} vs {}",
+                    quantile,
+                    relative_accuracy,
+                    estimate,
+                    exact
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn merging_gives_the_same_sketch() {
+        let values = (0..10_000u64).map(|i| i * i % 100_003).collect::<Vec<_>>();
+        let all = sketch(0.01, &values);
+
+        let mut merged = DDSketch::default();
+        for part in values.chunks(3_333) {
+            merged.merge(&sketch(0.01, part));
+        }
+        merged.merge(&DDSketch::default());
+        assert_eq!(merged, all);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot merge sketches with different relative accuracies")]
+    fn refuses_to_merge_different_accuracies() {
+        DDSketch::new(0.01).merge(&DDSketch::new(0.02));
+    }
+
+    #[test]
+    #[should_panic(expected = "relative accuracy must be between 0 and 1")]
+    fn refuses_invalid_accuracies() {
+        DDSketch::new(1.0);
+    }
+}
@@ -1,36 +1,65 @@
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
 use std::fmt::Display;
 
-use crate::nginx_log::NginxLog;
+use crate::endpoints::{self, EndpointColumn, EndpointStats, EndpointTable};
+use crate::filter::Filter;
+use crate::nginx_log::{NginxLog, NginxLogLine};
+use crate::normalize::EndpointNormalizer;
+use crate::series::{BucketStats, BucketTable, BucketWidth};
+use crate::sketch::DDSketch;
+use crate::top::{TopClients, TopClientsTable, TopCounter, TopValues};
 
 /// Represents statistics about an Nginx log
 ///
+/// Metrics are `None` when there are no requests of that kind to compute them from, such as the
+/// failed request metrics of a log with no failures.
+///
 /// # Contains
 ///
 /// * `status_count` - A map of status codes to the number of times they were returned
+/// * `sum_all_requests` - The total number of bytes returned for all requests
+/// * `sum_successful_requests` - The total number of bytes returned for successful requests
+/// * `sum_failed_requests` - The total number of bytes returned for failed requests
 /// * `mean_all_requests` - The mean number of bytes returned for all requests
 /// * `mean_successful_requests` - The mean number of bytes returned for successful requests
 /// * `mean_failed_requests` - The mean number of bytes returned for failed requests
-/// * `median_all_requests` - The median number of bytes returned for all requests
-/// * `median_successful_requests` - The median number of bytes returned for successful requests
-/// * `median_failed_requests` - The median number of bytes returned for failed requests
-/// * `p99_all_requests` - The 99th percentile of bytes returned for all requests
-/// * `p99_successful_requests` - The 99th percentile of bytes returned for successful requests
-/// * `p99_failed_requests` - The 99th percentile of bytes returned for failed requests
+/// * `percentiles` - Percentiles of bytes returned for each class of requests, in increasing
+///   order
+/// * `request_time` - Statistics about `$request_time`, if the log has it
+/// * `upstream_response_time` - Statistics about `$upstream_response_time`, if the log has it
 /// * `largest_endpoint` - The enpoint that returned the largest response
 /// * `failingest_endpoint` - The endpoint that returned the most failed responses
+/// * `endpoint_count` - A map of endpoints to the number of requests made to them
+/// * `endpoint_failures` - A map of endpoints to the number of failed responses they returned
+/// * `endpoints` - Detailed statistics about each endpoint, if they were requested with
+///   [`LogStatsBuilder::per_endpoint`]
+/// * `buckets` - Statistics about each time bucket with requests in it, oldest first, if they
+///   were requested with [`LogStatsBuilder::bucketed`]
+/// * `clients` - The client IPs, user agents and referrers with the most requests, bytes and
+///   errors, if they were requested with [`LogStatsBuilder::top_clients`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct LogStats {
-    pub status_count: std::collections::BTreeMap<u16, usize>,
-    pub mean_all_requests: f64,
-    pub mean_successful_requests: f64,
-    pub mean_failed_requests: f64,
-    pub median_all_requests: f64,
-    pub median_successful_requests: f64,
-    pub median_failed_requests: f64,
-    pub p99_all_requests: f64,
-    pub p99_successful_requests: f64,
-    pub p99_failed_requests: f64,
-    pub largest_endpoint: String,
-    pub failingest_endpoint: String,
+    pub status_count: BTreeMap<u16, usize>,
+    pub sum_all_requests: u128,
+    pub sum_successful_requests: u128,
+    pub sum_failed_requests: u128,
+    pub mean_all_requests: Option<f64>,
+    pub mean_successful_requests: Option<f64>,
+    pub mean_failed_requests: Option<f64>,
+    pub percentiles: Vec<Percentile>,
+    pub request_time: Option<LatencyStats>,
+    pub upstream_response_time: Option<LatencyStats>,
+    pub largest_endpoint: Option<String>,
+    pub failingest_endpoint: Option<String>,
+    pub endpoint_count: BTreeMap<String, usize>,
+    pub endpoint_failures: BTreeMap<String, usize>,
+    pub endpoints: Option<Vec<EndpointStats>>,
+    pub buckets: Option<Vec<BucketStats>>,
+    pub clients: Option<TopClients>,
 }
 
 impl LogStats {
@@ -44,60 +73,1029 @@ impl LogStats {
     ///
     /// A new LogStats instance
     pub fn from_nginx_log(log: &NginxLog) -> Self {
-        let mut status_count = std::collections::BTreeMap::new();
-        let mut largest_endpoint = ("".to_owned(), 0);
-        let mut endpoint_failures = std::collections::BTreeMap::new();
-        let mut all_bytes = Vec::new();
-
+        let mut builder = LogStatsBuilder::new();
         for line in &log.0 {
-            let endpoint = line.request.split_whitespace().nth(1).unwrap_or("/");
-            *status_count.entry(line.response).or_insert(0) += 1;
-            all_bytes.push(line.bytes);
+            builder.add(line);
+        }
+        builder.build()
+    }
+
+    /// Returns the given percentile of bytes returned, if it was computed
+    ///
+    /// # Arguments
+    ///
+    /// * `percentile` - The percentile, between 0 and 100
+    pub fn percentile(&self, percentile: f64) -> Option<&Percentile> {
+        self.percentiles
+            .iter()
+            .find(|computed| computed.percentile == percentile)
+    }
+
+    /// Sorts the per-endpoint stats by a column and keeps only the first of them
+    ///
+    /// Does nothing if the stats were built without per-endpoint stats. See [`endpoints::sort`]
+    /// for the order.
+    ///
+    /// # Arguments
+    ///
+    /// * `column` - The column to sort by
+    /// * `limit` - The number of endpoints to keep, or `None` to keep them all
+    pub fn sort_endpoints(&mut self, column: &EndpointColumn, limit: Option<usize>) {
+        if let Some(stats) = &mut self.endpoints {
+            endpoints::sort(stats, column);
+            stats.truncate(limit.unwrap_or(usize::MAX));
+        }
+    }
+}
+
+/// Statistics about one of the timings of requests, in seconds
+///
+/// Only requests with the timing are counted, so for `$upstream_response_time` requests served
+/// without an upstream are left out.
+///
+/// # Contains
+///
+/// * `count_all_requests` - The number of requests with the timing
+/// * `count_successful_requests` - The number of successful requests with the timing
+/// * `count_failed_requests` - The number of failed requests with the timing
+/// * `sum_all_requests` - The total time of all requests
+/// * `sum_successful_requests` - The total time of successful requests
+/// * `sum_failed_requests` - The total time of failed requests
+/// * `mean_all_requests` - The mean time of all requests
+/// * `mean_successful_requests` - The mean time of successful requests
+/// * `mean_failed_requests` - The mean time of failed requests
+/// * `percentiles` - Percentiles of the time of each class of requests, in increasing order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LatencyStats {
+    pub count_all_requests: usize,
+    pub count_successful_requests: usize,
+    pub count_failed_requests: usize,
+    pub sum_all_requests: f64,
+    pub sum_successful_requests: f64,
+    pub sum_failed_requests: f64,
+    pub mean_all_requests: Option<f64>,
+    pub mean_successful_requests: Option<f64>,
+    pub mean_failed_requests: Option<f64>,
+    pub percentiles: Vec<Percentile>,
+}
+
+/// Statistics about a group of requests, such as those to one endpoint or in one time bucket
+///
+/// # Contains
+///
+/// * `requests` - The number of requests
+/// * `errors` - The number of failed responses
+/// * `error_rate` - The fraction of requests that failed, between 0 and 1
+/// * `status_classes` - A map of status classes such as `2xx` to the number of responses in them
+/// * `bytes` - Statistics about the bytes returned
+/// * `request_time` - Statistics about `$request_time` in seconds, if the log has it
+/// * `upstream_response_time` - Statistics about `$upstream_response_time` in seconds, if the
+///   log has it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequestStats {
+    pub requests: usize,
+    pub errors: usize,
+    pub error_rate: f64,
+    pub status_classes: BTreeMap<String, usize>,
+    pub bytes: Summary,
+    pub request_time: Option<Summary>,
+    pub upstream_response_time: Option<Summary>,
+}
+
+/// The count, mean and percentiles of a set of values
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub percentiles: Vec<PercentileValue>,
+}
+
+/// A single percentile of a [`Summary`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PercentileValue {
+    pub percentile: f64,
+    pub value: f64,
+}
+
+impl Summary {
+    /// Returns the value at the given percentile, if it was computed
+    pub fn percentile(&self, percentile: f64) -> Option<f64> {
+        self.percentiles
+            .iter()
+            .find(|computed| computed.percentile == percentile)
+            .map(|computed| computed.value)
+    }
+}
+
+/// A percentile of bytes returned, or of a timing, for each class of requests
+///
+/// The `p`th percentile of `n` sorted values is the value at zero-based index `floor(n * p / 100)`,
+/// capped at the last value. Unlike the nearest-rank method, this takes the next value when
+/// `n * p / 100` is a whole number, so the median of 1 to 100 is 51 rather than 50. The 0th
+/// percentile is the smallest value and the 100th the largest.
+///
+/// # Contains
+///
+/// * `percentile` - The percentile, between 0 and 100
+/// * `all_requests` - The percentile for all requests
+/// * `successful_requests` - The percentile for successful requests
+/// * `failed_requests` - The percentile for failed requests
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Percentile {
+    pub percentile: f64,
+    pub all_requests: Option<f64>,
+    pub successful_requests: Option<f64>,
+    pub failed_requests: Option<f64>,
+}
+
+impl Percentile {
+    /// Returns the percentile as a quantile between 0 and 1
+    ///
+    /// Rounded so that, for example, the 99.9th percentile is exactly `0.999`.
+    pub fn quantile(&self) -> f64 {
+        quantile(self.percentile)
+    }
 
-            if line.response >= 400 {
-                *endpoint_failures.entry(endpoint.to_owned()).or_insert(0) += 1;
+    /// Returns a human readable name for the percentile, such as `Median` or `99th Percentile`
+    pub fn name(&self) -> String {
+        let percentile = self.percentile;
+        if percentile == 0.0 {
+            return "Min".to_owned();
+        } else if percentile == 50.0 {
+            return "Median".to_owned();
+        } else if percentile == 100.0 {
+            return "Max".to_owned();
+        }
+
+        let suffix = if percentile.fract() != 0.0 {
+            "th"
+        } else {
+            match (percentile as u64 % 100, percentile as u64 % 10) {
+                (11..=13, _) => "th",
+                (_, 1) => "st",
+                (_, 2) => "nd",
+                (_, 3) => "rd",
+                _ => "th",
             }
+        };
+        format!("{}{} Percentile", percentile, suffix)
+    }
+}
+
+/// Converts a percentile between 0 and 100 to a quantile, without floating point noise
+fn quantile(percentile: f64) -> f64 {
+    (percentile * 1e7).round() / 1e9
+}
 
-            if line.bytes > largest_endpoint.1 {
-                largest_endpoint = (endpoint.to_owned(), line.bytes);
+/// Accumulates statistics one log line at a time
+///
+/// Only the aggregates and the byte count and timings of each line are kept, so a log can be
+/// streamed through the builder with an [`NginxLogReader`](crate::nginx_log::NginxLogReader)
+/// without holding its lines in memory. Byte counts and timings can also be summarized in a
+/// [`DDSketch`] to bound memory, see [`LogStatsBuilder::with_sketch`].
+///
+/// Builders can be filled separately, for example one per file or thread, and combined with
+/// [`LogStatsBuilder::merge`].
+#[derive(Debug, Clone)]
+pub struct LogStatsBuilder {
+    status_count: BTreeMap<u16, usize>,
+    largest_endpoint: Option<(String, u64)>,
+    endpoint_count: BTreeMap<String, usize>,
+    endpoint_failures: BTreeMap<String, usize>,
+    bytes: ByClass,
+    /// Timings in microseconds
+    request_time: ByClass,
+    upstream_response_time: ByClass,
+    percentiles: Vec<f64>,
+    endpoints: Option<BTreeMap<String, GroupBuilder>>,
+    buckets: Option<Buckets>,
+    normalizer: Option<EndpointNormalizer>,
+    filter: Option<Filter>,
+    clients: Option<Clients>,
+}
+
+/// The time buckets of a [`LogStatsBuilder`]
+#[derive(Debug, Clone)]
+struct Buckets {
+    width: BucketWidth,
+    /// The time zone of the first line, which bucket times are shown in
+    offset: Option<FixedOffset>,
+    /// Buckets by their start in seconds since the Unix epoch
+    groups: BTreeMap<i64, GroupBuilder>,
+}
+
+/// The top client counters of a [`LogStatsBuilder`]
+#[derive(Debug, Clone)]
+struct Clients {
+    /// The number of values to report for each field and count
+    limit: usize,
+    remote_ip: TopBuilder,
+    agent: TopBuilder,
+    referrer: TopBuilder,
+}
+
+impl Clients {
+    fn new_like(&self) -> Self {
+        Self {
+            limit: self.limit,
+            remote_ip: self.remote_ip.new_like(),
+            agent: self.agent.new_like(),
+            referrer: self.referrer.new_like(),
+        }
+    }
+
+    fn add(&mut self, line: &NginxLogLine) {
+        for (counters, value) in [
+            (&mut self.remote_ip, &line.remote_ip),
+            (&mut self.agent, &line.agent),
+            (&mut self.referrer, &line.referrer),
+        ] {
+            if !value.is_empty() && value != "-" {
+                counters.add(value, line);
             }
         }
-        all_bytes.sort();
-        let mut failed_bytes = log
-            .0
-            .iter()
-            .filter(|line| line.response >= 400)
-            .map(|line| line.bytes)
-            .collect::<Vec<u64>>();
-        failed_bytes.sort();
-        let mut success_bytes = log
-            .0
-            .iter()
-            .filter(|line| line.response < 400)
-            .map(|line| line.bytes)
-            .collect::<Vec<u64>>();
-        success_bytes.sort();
+    }
+
+    fn merge(&mut self, other: Clients) {
+        self.remote_ip.merge(other.remote_ip);
+        self.agent.merge(other.agent);
+        self.referrer.merge(other.referrer);
+    }
+
+    fn build(self) -> TopClients {
+        TopClients {
+            remote_ip: self.remote_ip.build(self.limit),
+            agent: self.agent.build(self.limit),
+            referrer: self.referrer.build(self.limit),
+        }
+    }
+}
+
+/// Counts the requests, bytes and errors of each value of one field
+#[derive(Debug, Clone)]
+struct TopBuilder {
+    requests: TopCounter,
+    bytes: TopCounter,
+    errors: TopCounter,
+}
+
+impl TopBuilder {
+    fn new(capacity: Option<usize>) -> Self {
+        Self {
+            requests: TopCounter::new(capacity),
+            bytes: TopCounter::new(capacity),
+            errors: TopCounter::new(capacity),
+        }
+    }
+
+    fn new_like(&self) -> Self {
+        Self {
+            requests: self.requests.new_like(),
+            bytes: self.bytes.new_like(),
+            errors: self.errors.new_like(),
+        }
+    }
+
+    fn add(&mut self, value: &str, line: &NginxLogLine) {
+        self.requests.add(value, 1);
+        self.bytes.add(value, line.bytes);
+        self.errors.add(value, u64::from(line.response >= 400));
+    }
+
+    fn merge(&mut self, other: TopBuilder) {
+        self.requests.merge(other.requests);
+        self.bytes.merge(other.bytes);
+        self.errors.merge(other.errors);
+    }
+
+    fn build(self, limit: usize) -> TopValues {
+        TopValues {
+            requests: self.requests.top(limit),
+            bytes: self.bytes.top(limit),
+            errors: self.errors.top(limit),
+        }
+    }
+}
 
+impl Default for LogStatsBuilder {
+    fn default() -> Self {
         Self {
+            status_count: BTreeMap::new(),
+            largest_endpoint: None,
+            endpoint_count: BTreeMap::new(),
+            endpoint_failures: BTreeMap::new(),
+            bytes: ByClass::default(),
+            request_time: ByClass::default(),
+            upstream_response_time: ByClass::default(),
+            percentiles: Self::DEFAULT_PERCENTILES.to_vec(),
+            endpoints: None,
+            buckets: None,
+            normalizer: None,
+            filter: None,
+            clients: None,
+        }
+    }
+}
+
+impl LogStatsBuilder {
+    /// The number of byte counts per class of requests kept exactly before switching to a sketch
+    pub const EXACT_LIMIT: usize = 1_000_000;
+
+    /// The percentiles computed unless others are chosen with [`Self::percentiles`]
+    pub const DEFAULT_PERCENTILES: [f64; 2] = [50.0, 99.0];
+
+    /// Creates a new empty LogStatsBuilder
+    ///
+    /// Percentiles are exact until a class of requests has more than [`Self::EXACT_LIMIT`] lines,
+    /// then estimated with a [`DDSketch`] at its default relative accuracy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new empty LogStatsBuilder like [`Self::new`] with a different sketch accuracy
+    ///
+    /// # Arguments
+    ///
+    /// * `relative_accuracy` - The maximum relative error of percentiles once they are estimated,
+    ///   between 0 and 1
+    pub fn with_relative_accuracy(relative_accuracy: f64) -> Self {
+        Self::with_distribution(Distribution::exact(Some(Promotion {
+            exact_limit: Self::EXACT_LIMIT,
+            relative_accuracy,
+        })))
+    }
+
+    /// Creates a new empty LogStatsBuilder that always computes exact percentiles
+    ///
+    /// Every byte count and timing is kept in memory until the stats are built.
+    pub fn exact() -> Self {
+        Self::with_distribution(Distribution::exact(None))
+    }
+
+    /// Creates a new empty LogStatsBuilder that always estimates percentiles with a [`DDSketch`]
+    ///
+    /// # Arguments
+    ///
+    /// * `relative_accuracy` - The maximum relative error of percentiles, between 0 and 1
+    pub fn with_sketch(relative_accuracy: f64) -> Self {
+        Self::with_distribution(Distribution::sketch(relative_accuracy))
+    }
+
+    fn with_distribution(distribution: Distribution) -> Self {
+        Self {
+            bytes: ByClass::new(distribution.clone()),
+            request_time: ByClass::new(distribution.clone()),
+            upstream_response_time: ByClass::new(distribution),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the percentiles to compute, replacing the defaults
+    ///
+    /// They are computed in increasing order, and duplicates are computed once.
+    ///
+    /// # Arguments
+    ///
+    /// * `percentiles` - The percentiles to compute, between 0 and 100
+    ///
+    /// # Panics
+    ///
+    /// Panics if a percentile is not between 0 and 100
+    pub fn percentiles(mut self, percentiles: &[f64]) -> Self {
+        assert!(
+            percentiles.iter().all(|p| (0.0..=100.0).contains(p)),
+            "percentiles must be between 0 and 100"
+        );
+
+        self.percentiles = percentiles.to_vec();
+        self.percentiles.sort_by(f64::total_cmp);
+        self.percentiles.dedup();
+        self
+    }
+
+    /// Also computes [`EndpointStats`] for every endpoint
+    ///
+    /// Byte counts and timings are then kept for each endpoint as well as for the whole log, so
+    /// this can double the memory used for exact percentiles.
+    pub fn per_endpoint(mut self) -> Self {
+        self.endpoints = Some(BTreeMap::new());
+        self
+    }
+
+    /// Also computes [`BucketStats`] for every time bucket with requests in it
+    ///
+    /// Like [`Self::per_endpoint`], byte counts and timings are then kept for each bucket as well
+    /// as for the whole log.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of each bucket
+    pub fn bucketed(mut self, width: BucketWidth) -> Self {
+        self.buckets = Some(Buckets {
+            width,
+            offset: None,
+            groups: BTreeMap::new(),
+        });
+        self
+    }
+
+    /// Rewrites endpoints into route templates before counting them
+    ///
+    /// Applies to every per-endpoint statistic, including the largest and failingest endpoints.
+    ///
+    /// # Arguments
+    ///
+    /// * `normalizer` - The rules to rewrite endpoints with
+    pub fn normalizer(mut self, normalizer: EndpointNormalizer) -> Self {
+        self.normalizer = Some(normalizer);
+        self
+    }
+
+    /// Only counts lines that match a filter, ignoring every other line
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter lines must match
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Also finds the client IPs, user agents and referrers with the most requests, bytes and
+    /// errors
+    ///
+    /// Each field is counted exactly until it has more than `capacity` distinct values, then
+    /// approximately with a [`SpaceSaving`](crate::top::SpaceSaving) summary of that many values,
+    /// which bounds memory on huge logs. Approximate counts can only be too high, and each comes
+    /// with the most it can be off by.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The number of values to report for each field and count
+    /// * `capacity` - The number of distinct values to count per field and count, or `None` to
+    ///   always count exactly
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0
+    pub fn top_clients(mut self, limit: usize, capacity: Option<usize>) -> Self {
+        self.clients = Some(Clients {
+            limit,
+            remote_ip: TopBuilder::new(capacity),
+            agent: TopBuilder::new(capacity),
+            referrer: TopBuilder::new(capacity),
+        });
+        self
+    }
+
+    /// Creates a new empty LogStatsBuilder with the same settings as this one
+    pub fn new_like(&self) -> Self {
+        Self {
+            bytes: self.bytes.new_like(),
+            request_time: self.request_time.new_like(),
+            upstream_response_time: self.upstream_response_time.new_like(),
+            percentiles: self.percentiles.clone(),
+            endpoints: self.endpoints.as_ref().map(|_| BTreeMap::new()),
+            buckets: self.buckets.as_ref().map(|buckets| Buckets {
+                width: buckets.width,
+                offset: None,
+                groups: BTreeMap::new(),
+            }),
+            normalizer: self.normalizer.clone(),
+            filter: self.filter.clone(),
+            clients: self.clients.as_ref().map(Clients::new_like),
+            ..Self::default()
+        }
+    }
+
+    /// Adds a single log line to the statistics
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The log line to add
+    pub fn add(&mut self, line: &NginxLogLine) {
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(line))
+        {
+            return;
+        }
+
+        let mut endpoint = line.endpoint();
+        if let Some(normalizer) = &self.normalizer {
+            if let Cow::Owned(normalized) = normalizer.normalize(&endpoint) {
+                endpoint = normalized;
+            }
+        }
+        let failed = line.response >= 400;
+        *self.status_count.entry(line.response).or_insert(0) += 1;
+        *self.endpoint_count.entry(endpoint.clone()).or_insert(0) += 1;
+        if failed {
+            *self.endpoint_failures.entry(endpoint.clone()).or_insert(0) += 1;
+        }
+
+        self.bytes.add(failed, line.bytes);
+        if let Some(time) = line.request_time {
+            self.request_time.add(failed, micros(time));
+        }
+        if let Some(time) = line.upstream_response_time {
+            self.upstream_response_time.add(failed, micros(time));
+        }
+
+        if let Some(endpoints) = &mut self.endpoints {
+            endpoints
+                .entry(endpoint.clone())
+                .or_insert_with(|| GroupBuilder::new(self.bytes.successful.new_like()))
+                .add(line);
+        }
+        if let Some(buckets) = &mut self.buckets {
+            buckets.offset.get_or_insert(*line.time.offset());
+            buckets
+                .groups
+                .entry(buckets.width.bucket(&line.time))
+                .or_insert_with(|| GroupBuilder::new(self.bytes.successful.new_like()))
+                .add(line);
+        }
+        if let Some(clients) = &mut self.clients {
+            clients.add(line);
+        }
+
+        if self
+            .largest_endpoint
+            .as_ref()
+            .is_none_or(|(_, bytes)| line.bytes > *bytes)
+        {
+            self.largest_endpoint = Some((endpoint, line.bytes));
+        }
+    }
+
+    /// Adds all lines from another builder to this one
+    ///
+    /// Merging is associative, and merging builders filled from consecutive parts of a log in
+    /// order gives the same statistics as adding every line to a single builder. If either
+    /// builder uses a sketch, the merged percentiles are estimated with a sketch. The percentiles
+    /// to compute are kept from this builder. Neither holds exactly for top clients once they are
+    /// counted approximately, whose counts then stay within their error instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The builder to merge into this one
+    pub fn merge(&mut self, other: LogStatsBuilder) {
+        merge_counts(&mut self.status_count, other.status_count);
+        merge_counts(&mut self.endpoint_count, other.endpoint_count);
+        merge_counts(&mut self.endpoint_failures, other.endpoint_failures);
+
+        if let Some((endpoint, bytes)) = other.largest_endpoint {
+            if self
+                .largest_endpoint
+                .as_ref()
+                .is_none_or(|(_, largest)| bytes > *largest)
+            {
+                self.largest_endpoint = Some((endpoint, bytes));
+            }
+        }
+
+        self.bytes.merge(other.bytes);
+        self.request_time.merge(other.request_time);
+        self.upstream_response_time
+            .merge(other.upstream_response_time);
+
+        match (&mut self.endpoints, other.endpoints) {
+            (Some(endpoints), Some(other)) => merge_groups(endpoints, other),
+            (endpoints @ None, Some(other)) => *endpoints = Some(other),
+            (_, None) => {}
+        }
+        match (&mut self.buckets, other.buckets) {
+            (Some(buckets), Some(other)) => {
+                buckets.offset = buckets.offset.or(other.offset);
+                merge_groups(&mut buckets.groups, other.groups);
+            }
+            (buckets @ None, Some(other)) => *buckets = Some(other),
+            (_, None) => {}
+        }
+        match (&mut self.clients, other.clients) {
+            (Some(clients), Some(other)) => clients.merge(other),
+            (clients @ None, Some(other)) => *clients = Some(other),
+            (_, None) => {}
+        }
+    }
+
+    /// Computes the final statistics from all added lines
+    ///
+    /// # Returns
+    ///
+    /// A new LogStats instance
+    pub fn build(self) -> LogStats {
+        let Self {
+            status_count,
+            largest_endpoint,
+            endpoint_count,
+            endpoint_failures,
+            bytes,
+            request_time,
+            upstream_response_time,
+            percentiles,
+            endpoints,
+            buckets,
+            normalizer: _,
+            filter: _,
+            clients,
+        } = self;
+
+        let (sum_successful_requests, sum_failed_requests) =
+            (bytes.successful.sum, bytes.failed.sum);
+        let bytes = bytes.summarize(&percentiles, 1.0);
+        let latency = |times: ByClass| {
+            (times.successful.count + times.failed.count > 0)
+                .then(|| times.summarize(&percentiles, MICROS_PER_SECOND))
+        };
+
+        LogStats {
             status_count,
-            mean_all_requests: all_bytes.iter().sum::<u64>() as f64 / all_bytes.len() as f64,
-            mean_successful_requests: success_bytes.iter().sum::<u64>() as f64
-                / success_bytes.len() as f64,
-            mean_failed_requests: failed_bytes.iter().sum::<u64>() as f64
-                / failed_bytes.len() as f64,
-            median_all_requests: all_bytes[all_bytes.len() / 2] as f64,
-            median_successful_requests: success_bytes[success_bytes.len() / 2] as f64,
-            median_failed_requests: failed_bytes[failed_bytes.len() / 2] as f64,
-            p99_all_requests: all_bytes[(all_bytes.len() as f64 * 0.99) as usize] as f64,
-            p99_successful_requests: success_bytes[(success_bytes.len() as f64 * 0.99) as usize]
-                as f64,
-            p99_failed_requests: failed_bytes[(failed_bytes.len() as f64 * 0.99) as usize] as f64,
-            largest_endpoint: largest_endpoint.0,
+            sum_all_requests: sum_successful_requests + sum_failed_requests,
+            sum_successful_requests,
+            sum_failed_requests,
+            mean_all_requests: bytes.mean_all_requests,
+            mean_successful_requests: bytes.mean_successful_requests,
+            mean_failed_requests: bytes.mean_failed_requests,
+            percentiles: bytes.percentiles,
+            request_time: latency(request_time),
+            upstream_response_time: latency(upstream_response_time),
+            largest_endpoint: largest_endpoint.map(|(endpoint, _)| endpoint),
             failingest_endpoint: endpoint_failures
                 .iter()
                 .max_by_key(|&(_, count)| count)
-                .map(|(endpoint, _)| endpoint.to_owned())
-                .unwrap_or("/".to_owned()),
+                .map(|(endpoint, _)| endpoint.to_owned()),
+            endpoint_count,
+            endpoint_failures,
+            endpoints: endpoints.map(|endpoints| {
+                endpoints
+                    .into_iter()
+                    .map(|(endpoint, stats)| EndpointStats {
+                        endpoint,
+                        stats: stats.build(&percentiles),
+                    })
+                    .collect()
+            }),
+            buckets: buckets.map(|buckets| {
+                let offset = buckets.offset.unwrap_or(FixedOffset::east_opt(0).unwrap());
+                let time = |seconds: i64| {
+                    DateTime::from_timestamp(seconds, 0)
+                        .unwrap_or_default()
+                        .with_timezone(&offset)
+                };
+                let width = buckets.width.seconds();
+                buckets
+                    .groups
+                    .into_iter()
+                    .map(|(start, stats)| {
+                        let stats = stats.build(&percentiles);
+                        BucketStats {
+                            start: time(start),
+                            end: time(start.saturating_add_unsigned(width)),
+                            requests_per_second: stats.requests as f64 / width as f64,
+                            stats,
+                        }
+                    })
+                    .collect()
+            }),
+            clients: clients.map(Clients::build),
+        }
+    }
+}
+
+/// Merges the groups of requests from one map into another
+fn merge_groups<K: Ord>(groups: &mut BTreeMap<K, GroupBuilder>, other: BTreeMap<K, GroupBuilder>) {
+    for (key, stats) in other {
+        match groups.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(stats);
+            }
+            Entry::Occupied(mut entry) => entry.get_mut().merge(stats),
+        }
+    }
+}
+
+/// Accumulates the statistics of a group of requests, such as those to a single endpoint
+#[derive(Debug, Clone)]
+struct GroupBuilder {
+    status_count: BTreeMap<u16, usize>,
+    bytes: Distribution,
+    /// Timings in microseconds
+    request_time: Distribution,
+    upstream_response_time: Distribution,
+}
+
+impl GroupBuilder {
+    fn new(distribution: Distribution) -> Self {
+        Self {
+            status_count: BTreeMap::new(),
+            bytes: distribution.clone(),
+            request_time: distribution.clone(),
+            upstream_response_time: distribution,
+        }
+    }
+
+    fn add(&mut self, line: &NginxLogLine) {
+        *self.status_count.entry(line.response).or_insert(0) += 1;
+        self.bytes.add(line.bytes);
+        if let Some(time) = line.request_time {
+            self.request_time.add(micros(time));
+        }
+        if let Some(time) = line.upstream_response_time {
+            self.upstream_response_time.add(micros(time));
+        }
+    }
+
+    fn merge(&mut self, other: GroupBuilder) {
+        merge_counts(&mut self.status_count, other.status_count);
+        self.bytes.merge(other.bytes);
+        self.request_time.merge(other.request_time);
+        self.upstream_response_time
+            .merge(other.upstream_response_time);
+    }
+
+    fn build(self, percentiles: &[f64]) -> RequestStats {
+        let requests = self.status_count.values().sum::<usize>();
+        let errors = self.status_count.range(400..).map(|(_, count)| count).sum();
+        let mut status_classes = BTreeMap::new();
+        for (status, count) in &self.status_count {
+            *status_classes
+                .entry(format!("{}xx", status / 100))
+                .or_insert(0) += count;
+        }
+
+        RequestStats {
+            requests,
+            errors,
+            error_rate: errors as f64 / requests as f64,
+            status_classes,
+            bytes: self
+                .bytes
+                .summary(percentiles, 1.0)
+                .expect("every request has a byte count"),
+            request_time: self.request_time.summary(percentiles, MICROS_PER_SECOND),
+            upstream_response_time: self
+                .upstream_response_time
+                .summary(percentiles, MICROS_PER_SECOND),
+        }
+    }
+}
+
+const MICROS_PER_SECOND: f64 = 1_000_000.0;
+
+/// Converts a timing to the whole microseconds it is kept as
+fn micros(duration: std::time::Duration) -> u64 {
+    duration.as_micros().try_into().unwrap_or(u64::MAX)
+}
+
+/// Adds the counts from one map to another
+fn merge_counts<K: Ord>(counts: &mut BTreeMap<K, usize>, other: BTreeMap<K, usize>) {
+    for (key, count) in other {
+        *counts.entry(key).or_insert(0) += count;
+    }
+}
+
+/// The values of one metric, split into successful and failed requests
+#[derive(Debug, Clone, Default)]
+struct ByClass {
+    successful: Distribution,
+    failed: Distribution,
+}
+
+impl ByClass {
+    fn new(distribution: Distribution) -> Self {
+        Self {
+            successful: distribution.clone(),
+            failed: distribution,
+        }
+    }
+
+    fn new_like(&self) -> Self {
+        Self {
+            successful: self.successful.new_like(),
+            failed: self.failed.new_like(),
+        }
+    }
+
+    fn add(&mut self, failed: bool, value: u64) {
+        if failed {
+            self.failed.add(value);
+        } else {
+            self.successful.add(value);
+        }
+    }
+
+    fn merge(&mut self, other: ByClass) {
+        self.successful.merge(other.successful);
+        self.failed.merge(other.failed);
+    }
+
+    /// Computes the counts, means and percentiles of each class, with values divided by `scale`
+    fn summarize(self, percentiles: &[f64], scale: f64) -> LatencyStats {
+        let Self {
+            mut successful,
+            mut failed,
+        } = self;
+
+        let mut all = successful.clone();
+        all.merge(failed.clone());
+        all.sort();
+        failed.sort();
+        successful.sort();
+
+        let scaled = |value: Option<f64>| value.map(|value| value / scale);
+        LatencyStats {
+            count_all_requests: all.count as usize,
+            count_successful_requests: successful.count as usize,
+            count_failed_requests: failed.count as usize,
+            sum_all_requests: all.sum as f64 / scale,
+            sum_successful_requests: successful.sum as f64 / scale,
+            sum_failed_requests: failed.sum as f64 / scale,
+            mean_all_requests: scaled(all.mean()),
+            mean_successful_requests: scaled(successful.mean()),
+            mean_failed_requests: scaled(failed.mean()),
+            percentiles: percentiles
+                .iter()
+                .map(|&percentile| Percentile {
+                    percentile,
+                    all_requests: scaled(all.quantile(quantile(percentile))),
+                    successful_requests: scaled(successful.quantile(quantile(percentile))),
+                    failed_requests: scaled(failed.quantile(quantile(percentile))),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The values of one class of requests, kept exactly or summarized in a sketch
+///
+/// The count and sum are always exact, so means are exact either way. The sum is wider than the
+/// values so that it cannot overflow.
+#[derive(Debug, Clone)]
+struct Distribution {
+    count: u64,
+    sum: u128,
+    values: Values,
+    /// When to switch exact values to a sketch, or `None` to always keep them exact
+    promotion: Option<Promotion>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Promotion {
+    exact_limit: usize,
+    relative_accuracy: f64,
+}
+
+impl Default for Distribution {
+    fn default() -> Self {
+        Self::exact(Some(Promotion {
+            exact_limit: LogStatsBuilder::EXACT_LIMIT,
+            relative_accuracy: DDSketch::DEFAULT_RELATIVE_ACCURACY,
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Values {
+    Exact(Vec<u64>),
+    Sketch(DDSketch),
+}
+
+impl Default for Values {
+    fn default() -> Self {
+        Self::Exact(Vec::new())
+    }
+}
+
+impl Distribution {
+    fn exact(promotion: Option<Promotion>) -> Self {
+        Self {
+            count: 0,
+            sum: 0,
+            values: Values::Exact(Vec::new()),
+            promotion,
+        }
+    }
+
+    fn sketch(relative_accuracy: f64) -> Self {
+        Self {
+            values: Values::Sketch(DDSketch::new(relative_accuracy)),
+            ..Self::exact(None)
+        }
+    }
+
+    /// Returns an empty distribution that starts out the way this one did
+    fn new_like(&self) -> Self {
+        match (&self.values, self.promotion) {
+            (Values::Sketch(sketch), None) => Self::sketch(sketch.relative_accuracy()),
+            (_, promotion) => Self::exact(promotion),
+        }
+    }
+
+    fn add(&mut self, value: u64) {
+        self.count += 1;
+        self.sum += u128::from(value);
+        match &mut self.values {
+            Values::Exact(values) => values.push(value),
+            Values::Sketch(sketch) => sketch.add(value),
+        }
+        self.promote();
+    }
+
+    /// Switches exact values to a sketch once there are too many of them
+    fn promote(&mut self) {
+        let (Values::Exact(values), Some(promotion)) = (&self.values, self.promotion) else {
+            return;
+        };
+        if values.len() <= promotion.exact_limit {
+            return;
+        }
+
+        let mut sketch = DDSketch::new(promotion.relative_accuracy);
+        values.iter().for_each(|value| sketch.add(*value));
+        self.values = Values::Sketch(sketch);
+    }
+
+    fn merge(&mut self, other: Distribution) {
+        self.count += other.count;
+        self.sum += other.sum;
+
+        let values = std::mem::take(&mut self.values);
+        self.values = match (values, other.values) {
+            (Values::Exact(mut values), Values::Exact(other)) => {
+                values.extend(other);
+                Values::Exact(values)
+            }
+            (Values::Sketch(mut sketch), Values::Sketch(other)) => {
+                sketch.merge(&other);
+                Values::Sketch(sketch)
+            }
+            (Values::Sketch(mut sketch), Values::Exact(values))
+            | (Values::Exact(values), Values::Sketch(mut sketch)) => {
+                values.into_iter().for_each(|value| sketch.add(value));
+                Values::Sketch(sketch)
+            }
+        };
+        self.promote();
+    }
+
+    /// Sorts exact values, which must be done before calling [`Distribution::quantile`]
+    fn sort(&mut self) {
+        if let Values::Exact(values) = &mut self.values {
+            values.sort();
+        }
+    }
+
+    fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.sum as f64 / self.count as f64)
+    }
+
+    fn quantile(&self, quantile: f64) -> Option<f64> {
+        match &self.values {
+            Values::Exact(sorted) => percentile(sorted, quantile),
+            Values::Sketch(sketch) => sketch.quantile(quantile),
+        }
+    }
+
+    /// Computes the count, mean and percentiles, with values divided by `scale`, or `None` if
+    /// there are no values
+    fn summary(mut self, percentiles: &[f64], scale: f64) -> Option<Summary> {
+        self.sort();
+        Some(Summary {
+            count: self.count as usize,
+            mean: self.mean()? / scale,
+            percentiles: percentiles
+                .iter()
+                .map(|&percentile| {
+                    Some(PercentileValue {
+                        percentile,
+                        value: self.quantile(quantile(percentile))? / scale,
+                    })
+                })
+                .collect::<Option<_>>()?,
+        })
+    }
+}
+
+/// Returns the value at the given quantile of sorted values, or `None` if there are none
+fn percentile(sorted: &[u64], quantile: f64) -> Option<f64> {
+    let index = ((sorted.len() as f64 * quantile) as usize).min(sorted.len().checked_sub(1)?);
+    Some(sorted[index] as f64)
+}
+
+/// Displays an optional metric, forwarding any formatting options, or `n/a` if it is missing
+struct Optional<'a, T>(&'a Option<T>);
+
+impl<T: Display> Display for Optional<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(value) => value.fmt(f),
+            None => f.write_str("n/a"),
         }
     }
 }
@@ -110,31 +1108,563 @@ impl Display for LogStats {
         }
 
         writeln!(f, "Mean Bytes:")?;
-        writeln!(f, "  All Requests: {:.2}", self.mean_all_requests)?;
+        writeln!(
+            f,
+            "  All Requests: {:.2}",
+            Optional(&self.mean_all_requests)
+        )?;
         writeln!(
             f,
             "  Successful Requests: {:.2}",
-            self.mean_successful_requests
+            Optional(&self.mean_successful_requests)
         )?;
-        writeln!(f, "  Failed Requests: {:.2}", self.mean_failed_requests)?;
-
-        writeln!(f, "Median Bytes:")?;
-        writeln!(f, "  All Requests: {}", self.median_all_requests)?;
         writeln!(
             f,
-            "  Successful Requests: {}",
-            self.median_successful_requests
+            "  Failed Requests: {:.2}",
+            Optional(&self.mean_failed_requests)
         )?;
-        writeln!(f, "  Failed Requests: {}", self.median_failed_requests)?;
 
-        writeln!(f, "99th Percentile Bytes:")?;
-        writeln!(f, "  All Requests: {}", self.p99_all_requests)?;
-        writeln!(f, "  Successful Requests: {}", self.p99_successful_requests)?;
-        writeln!(f, "  Failed Requests: {}", self.p99_failed_requests)?;
+        for percentile in &self.percentiles {
+            writeln!(f, "{} Bytes:", percentile.name())?;
+            writeln!(f, "  All Requests: {}", Optional(&percentile.all_requests))?;
+            writeln!(
+                f,
+                "  Successful Requests: {}",
+                Optional(&percentile.successful_requests)
+            )?;
+            writeln!(
+                f,
+                "  Failed Requests: {}",
+                Optional(&percentile.failed_requests)
+            )?;
+        }
+
+        let timings = [
+            ("Request Time", &self.request_time),
+            ("Upstream Response Time", &self.upstream_response_time),
+        ];
+        for (name, latency) in timings {
+            let Some(latency) = latency else {
+                continue;
+            };
+
+            writeln!(f, "Mean {} (s):", name)?;
+            writeln!(
+                f,
+                "  All Requests: {:.3}",
+                Optional(&latency.mean_all_requests)
+            )?;
+            writeln!(
+                f,
+                "  Successful Requests: {:.3}",
+                Optional(&latency.mean_successful_requests)
+            )?;
+            writeln!(
+                f,
+                "  Failed Requests: {:.3}",
+                Optional(&latency.mean_failed_requests)
+            )?;
+
+            for percentile in &latency.percentiles {
+                writeln!(f, "{} {} (s):", percentile.name(), name)?;
+                writeln!(
+                    f,
+                    "  All Requests: {:.3}",
+                    Optional(&percentile.all_requests)
+                )?;
+                writeln!(
+                    f,
+                    "  Successful Requests: {:.3}",
+                    Optional(&percentile.successful_requests)
+                )?;
+                writeln!(
+                    f,
+                    "  Failed Requests: {:.3}",
+                    Optional(&percentile.failed_requests)
+                )?;
+            }
+        }
+
+        writeln!(f, "Largest Endpoint: {}", Optional(&self.largest_endpoint))?;
+        writeln!(
+            f,
+            "Failingest Endpoint: {}",
+            Optional(&self.failingest_endpoint)
+        )?;
 
-        writeln!(f, "Largest Endpoint: {}", self.largest_endpoint)?;
-        writeln!(f, "Failingest Endpoint: {}", self.failingest_endpoint)?;
+        if let Some(endpoints) = &self.endpoints {
+            writeln!(f, "Endpoints:")?;
+            write!(f, "{}", EndpointTable(endpoints))?;
+        }
+        if let Some(buckets) = &self.buckets {
+            writeln!(f, "Buckets:")?;
+            write!(f, "{}", BucketTable(buckets))?;
+        }
+        if let Some(clients) = &self.clients {
+            write!(f, "{}", TopClientsTable(clients))?;
+        }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::top::TopValue;
+
+    fn line(request: &str, response: u16, bytes: u64) -> NginxLogLine {
+        NginxLogLine {
+            time: chrono::DateTime::UNIX_EPOCH.fixed_offset(),
+            remote_ip: "127.0.0.1".to_owned(),
+            remote_user: "-".to_owned(),
+            request: request.to_owned(),
+            response,
+            bytes,
+            referrer: "-".to_owned(),
+            agent: "-".to_owned(),
+            request_time: None,
+            upstream_response_time: None,
+            extra: BTreeMap::new(),
+        }
+    }
+
+    fn timed(response: u16, request_time: &str, upstream_response_time: &str) -> NginxLogLine {
+        NginxLogLine {
+            request_time: crate::duration::parse(request_time).unwrap(),
+            upstream_response_time: crate::duration::parse(upstream_response_time).unwrap(),
+            ..line("GET / HTTP/1.1", response, 0)
+        }
+    }
+
+    fn stats(lines: Vec<NginxLogLine>) -> LogStats {
+        LogStats::from_nginx_log(&NginxLog(lines))
+    }
+
+    fn at(stats: &LogStats, percentile: f64) -> &Percentile {
+        stats.percentile(percentile).unwrap()
+    }
+
+    #[test]
+    fn empty_log_has_no_metrics() {
+        let stats = stats(vec![]);
+
+        assert!(stats.status_count.is_empty());
+        assert_eq!(stats.mean_all_requests, None);
+        assert_eq!(stats.mean_successful_requests, None);
+        assert_eq!(stats.mean_failed_requests, None);
+        assert_eq!(at(&stats, 50.0).all_requests, None);
+        assert_eq!(at(&stats, 99.0).all_requests, None);
+        assert_eq!(stats.largest_endpoint, None);
+        assert_eq!(stats.failingest_endpoint, None);
+        assert_eq!(stats.request_time, None);
+        assert_eq!(stats.upstream_response_time, None);
+
+        let output = stats.to_string();
+        assert!(output.contains("  All Requests: n/a"));
+        assert!(output.contains("Largest Endpoint: n/a"));
+        assert!(!output.contains("NaN"));
+    }
+
+    #[test]
+    fn single_line() {
+        let stats = stats(vec![line("GET /index.html HTTP/1.1", 200, 512)]);
+
+        assert_eq!(stats.status_count, BTreeMap::from([(200, 1)]));
+        assert_eq!(stats.mean_all_requests, Some(512.0));
+        assert_eq!(at(&stats, 50.0).all_requests, Some(512.0));
+        assert_eq!(at(&stats, 99.0).all_requests, Some(512.0));
+        assert_eq!(at(&stats, 99.0).successful_requests, Some(512.0));
+        assert_eq!(stats.mean_failed_requests, None);
+        assert_eq!(stats.largest_endpoint.as_deref(), Some("/index.html"));
+        assert_eq!(stats.failingest_endpoint, None);
+    }
+
+    #[test]
+    fn all_successful_requests() {
+        let stats = stats(vec![
+            line("GET /a HTTP/1.1", 200, 100),
+            line("GET /b HTTP/1.1", 204, 0),
+            line("GET /c HTTP/1.1", 301, 300),
+        ]);
+
+        assert_eq!(stats.mean_successful_requests, Some(400.0 / 3.0));
+        assert_eq!(at(&stats, 50.0).successful_requests, Some(100.0));
+        assert_eq!(stats.mean_failed_requests, None);
+        assert_eq!(at(&stats, 50.0).failed_requests, None);
+        assert_eq!(at(&stats, 99.0).failed_requests, None);
+        assert_eq!(stats.largest_endpoint.as_deref(), Some("/c"));
+        assert_eq!(stats.failingest_endpoint, None);
+
+        let output = stats.to_string();
+        assert!(output.contains("  Failed Requests: n/a"));
+        assert!(output.contains("Failingest Endpoint: n/a"));
+    }
+
+    #[test]
+    fn all_failed_requests() {
+        let stats = stats(vec![
+            line("GET /a HTTP/1.1", 500, 10),
+            line("GET /b HTTP/1.1", 502, 20),
+            line("GET /b HTTP/1.1", 503, 30),
+        ]);
+
+        assert_eq!(stats.mean_failed_requests, Some(20.0));
+        assert_eq!(at(&stats, 50.0).failed_requests, Some(20.0));
+        assert_eq!(at(&stats, 99.0).failed_requests, Some(30.0));
+        assert_eq!(stats.mean_successful_requests, None);
+        assert_eq!(at(&stats, 50.0).successful_requests, None);
+        assert_eq!(stats.failingest_endpoint.as_deref(), Some("/b"));
+
+        let output = stats.to_string();
+        assert!(output.contains("  Successful Requests: n/a"));
+        assert!(output.contains("  Failed Requests: 20.00"));
+    }
+
+    #[test]
+    fn merged_builders_match_sequential() {
+        let lines = (0..1000)
+            .map(|i| {
+                let status = if i % 7 == 0 { 503 } else { 200 };
+                line(&format!("GET /{} HTTP/1.1", i % 13), status, i * 37 % 1009)
+            })
+            .collect::<Vec<_>>();
+
+        let mut sequential = LogStatsBuilder::new();
+        lines.iter().for_each(|line| sequential.add(line));
+
+        let mut merged = LogStatsBuilder::new();
+        for chunk in lines.chunks(97) {
+            let mut builder = LogStatsBuilder::new();
+            chunk.iter().for_each(|line| builder.add(line));
+            merged.merge(builder);
+        }
+
+        assert_eq!(merged.build(), sequential.build());
+    }
+
+    #[test]
+    fn sketch_percentiles_are_within_relative_accuracy() {
+        let lines = (1..=10_000)
+            .map(|bytes| line("GET / HTTP/1.1", 200, bytes * 3))
+            .collect::<Vec<_>>();
+
+        let mut exact = LogStatsBuilder::new();
+        let mut sketch = LogStatsBuilder::with_sketch(0.01);
+        for (i, line) in lines.iter().enumerate() {
+            exact.add(line);
+            // Merge in a second sketch halfway to check merged sketches keep the guarantee
+            if i < lines.len() / 2 {
+                sketch.add(line);
+            }
+        }
+        let mut rest = LogStatsBuilder::with_sketch(0.01);
+        lines[lines.len() / 2..]
+            .iter()
+            .for_each(|line| rest.add(line));
+        sketch.merge(rest);
+
+        let exact = exact.build();
+        let sketch = sketch.build();
+        assert_eq!(sketch.mean_all_requests, exact.mean_all_requests);
+        for (estimate, actual) in [
+            (
+                at(&sketch, 50.0).all_requests,
+                at(&exact, 50.0).all_requests,
+            ),
+            (
+                at(&sketch, 99.0).all_requests,
+                at(&exact, 99.0).all_requests,
+            ),
+        ] {
+            let (estimate, actual) = (estimate.unwrap(), actual.unwrap());
+            assert!((estimate - actual).abs() <= actual * 0.01);
+        }
+    }
+
+    #[test]
+    fn distributions_switch_to_a_sketch_past_the_exact_limit() {
+        let mut distribution = Distribution::default();
+        let limit = LogStatsBuilder::EXACT_LIMIT as u64;
+        (1..=limit).for_each(|value| distribution.add(value));
+        assert!(matches!(distribution.values, Values::Exact(_)));
+
+        distribution.add(limit + 1);
+        assert!(matches!(distribution.values, Values::Sketch(_)));
+        assert_eq!(distribution.count, limit + 1);
+        assert_eq!(
+            distribution.sum,
+            u128::from(limit + 1) * u128::from(limit + 2) / 2
+        );
+        assert_eq!(distribution.mean(), Some((limit + 2) as f64 / 2.0));
+        assert_eq!(distribution.quantile(1.0), Some((limit + 1) as f64));
+        let median = distribution.quantile(0.5).unwrap();
+        assert!((median - (limit / 2 + 1) as f64).abs() <= median * 0.01);
+
+        // Merging exact values past the limit switches too
+        let mut half = Distribution::default();
+        (0..limit / 2 + 1).for_each(|value| half.add(value));
+        let mut merged = half.clone();
+        merged.merge(half);
+        assert!(matches!(merged.values, Values::Sketch(_)));
+        assert_eq!(merged.count, limit + 2);
+    }
+
+    #[test]
+    fn sums_of_huge_responses_do_not_overflow() {
+        let huge = |response| line("GET / HTTP/1.1", response, u64::MAX);
+        let stats = stats(vec![huge(200), huge(200), huge(500)]);
+        assert_eq!(stats.sum_successful_requests, 2 * u128::from(u64::MAX));
+        assert_eq!(stats.sum_failed_requests, u128::from(u64::MAX));
+        assert_eq!(stats.sum_all_requests, 3 * u128::from(u64::MAX));
+        assert_eq!(stats.mean_all_requests, Some(u64::MAX as f64));
+
+        let mut merged = LogStatsBuilder::new();
+        for _ in 0..3 {
+            let mut builder = merged.new_like();
+            builder.add(&huge(200));
+            merged.merge(builder);
+        }
+        assert_eq!(merged.build().sum_all_requests, 3 * u128::from(u64::MAX));
+    }
+
+    #[test]
+    fn percentiles_index_the_floor_of_the_rank() {
+        let stats = stats(
+            (1..=100)
+                .map(|bytes| line("GET / HTTP/1.1", 200, bytes))
+                .collect(),
+        );
+
+        assert_eq!(at(&stats, 50.0).all_requests, Some(51.0));
+        assert_eq!(at(&stats, 99.0).all_requests, Some(100.0));
+    }
+
+    #[test]
+    fn custom_percentiles_are_ordered_and_named() {
+        let mut builder = LogStatsBuilder::new().percentiles(&[99.9, 50.0, 100.0, 90.0, 50.0]);
+        (1..=1000).for_each(|bytes| builder.add(&line("GET / HTTP/1.1", 200, bytes)));
+        let stats = builder.build();
+
+        let percentiles = stats
+            .percentiles
+            .iter()
+            .map(|p| (p.name(), p.all_requests))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            percentiles,
+            [
+                ("Median".to_owned(), Some(501.0)),
+                ("90th Percentile".to_owned(), Some(901.0)),
+                ("99.9th Percentile".to_owned(), Some(1000.0)),
+                ("Max".to_owned(), Some(1000.0)),
+            ]
+        );
+        assert!(stats.to_string().contains("99.9th Percentile Bytes:"));
+    }
+
+    #[test]
+    fn latency_by_class() {
+        let stats = stats(vec![
+            timed(200, "0.100", "0.090"),
+            timed(200, "0.300", "-"),
+            timed(502, "3.001", "1.000, 2.000 : -"),
+            line("GET / HTTP/1.1", 200, 0),
+        ]);
+
+        let request_time = stats.request_time.unwrap();
+        assert_eq!(request_time.count_all_requests, 3);
+        assert_eq!(request_time.mean_successful_requests, Some(0.2));
+        assert_eq!(request_time.mean_failed_requests, Some(3.001));
+        assert_eq!(request_time.percentiles[0].all_requests, Some(0.3));
+
+        let upstream = stats.upstream_response_time.unwrap();
+        assert_eq!(upstream.count_all_requests, 2);
+        assert_eq!(upstream.count_successful_requests, 1);
+        assert_eq!(upstream.mean_failed_requests, Some(3.0));
+        assert_eq!(upstream.percentiles[1].all_requests, Some(3.0));
+    }
+
+    #[test]
+    fn per_endpoint_stats_sorted_and_limited() {
+        let lines = [
+            line("GET /a HTTP/1.1", 200, 100),
+            line("GET /b HTTP/1.1", 500, 10),
+            line("GET /a HTTP/1.1", 404, 300),
+            line("GET /c HTTP/1.1", 200, 50),
+            line("GET /b HTTP/1.1", 503, 20),
+            timed(200, "0.5", "-"),
+        ];
+
+        let mut builder = LogStatsBuilder::new().per_endpoint();
+        lines[..3].iter().for_each(|line| builder.add(line));
+        let mut rest = builder.new_like();
+        lines[3..].iter().for_each(|line| rest.add(line));
+        builder.merge(rest);
+        let mut stats = builder.build();
+
+        let endpoints = stats.endpoints.as_ref().unwrap();
+        assert_eq!(endpoints.len(), 4);
+        let a = &endpoints[1].stats;
+        assert_eq!(
+            (endpoints[1].endpoint.as_str(), a.requests, a.errors),
+            ("/a", 2, 1)
+        );
+        assert_eq!(a.error_rate, 0.5);
+        assert_eq!(
+            a.status_classes,
+            BTreeMap::from([("2xx".to_owned(), 1), ("4xx".to_owned(), 1)])
+        );
+        assert_eq!(a.bytes.mean, 200.0);
+        assert_eq!(a.bytes.percentile(99.0), Some(300.0));
+        assert_eq!(a.request_time, None);
+        assert_eq!(endpoints[0].stats.request_time.as_ref().unwrap().mean, 0.5);
+
+        stats.sort_endpoints(&"errors".parse().unwrap(), Some(2));
+        let sorted = stats.endpoints.unwrap();
+        let sorted = sorted
+            .iter()
+            .map(|e| e.endpoint.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(sorted, ["/b", "/a"]);
+    }
+
+    #[test]
+    fn normalized_endpoints_are_counted_together() {
+        let rules = vec!["^/files/.*=/files/:path".parse().unwrap()];
+        let mut builder = LogStatsBuilder::new()
+            .per_endpoint()
+            .normalizer(EndpointNormalizer::new(true, rules));
+        for request in [
+            "GET /users/8812/orders/77 HTTP/1.1",
+            "GET /users/13/orders/9?page=2 HTTP/1.1",
+            "GET /sessions/3f2504e0-4f89-11d3-9a0c-0305e82c3301 HTTP/1.1",
+            "GET /blobs/d41d8cd98f00b204e9800998ecf8427e HTTP/1.1",
+            "GET /files/a/b.txt HTTP/1.1",
+            "GET /v2/users HTTP/1.1",
+        ] {
+            builder.add(&line(request, 500, 0));
+        }
+        let stats = builder.build();
+
+        assert_eq!(
+            stats.endpoint_failures,
+            BTreeMap::from([
+                ("/blobs/:hash".to_owned(), 1),
+                ("/files/:path".to_owned(), 1),
+                ("/sessions/:uuid".to_owned(), 1),
+                ("/users/:id/orders/:id".to_owned(), 2),
+                ("/v2/users".to_owned(), 1),
+            ])
+        );
+        assert_eq!(
+            stats.failingest_endpoint.as_deref(),
+            Some("/users/:id/orders/:id")
+        );
+        assert_eq!(stats.endpoints.unwrap().len(), 5);
+    }
+
+    #[test]
+    fn buckets_split_requests_by_time() {
+        let at = |time: &str, response: u16| NginxLogLine {
+            time: crate::timestamp::parse(time).unwrap(),
+            ..line("GET / HTTP/1.1", response, 100)
+        };
+        let width = "5m".parse::<BucketWidth>().unwrap();
+        assert_eq!(width.to_string(), "5m");
+
+        let mut builder = LogStatsBuilder::new().bucketed(width);
+        builder.add(&at("10/Oct/2026:13:59:59 +0200", 200));
+        let mut rest = builder.new_like();
+        rest.add(&at("10/Oct/2026:14:00:00 +0200", 200));
+        rest.add(&at("10/Oct/2026:14:04:59 +0200", 503));
+        rest.add(&at("10/Oct/2026:14:20:00 +0200", 200));
+        builder.merge(rest);
+        let buckets = builder.build().buckets.unwrap();
+
+        let summary = buckets
+            .iter()
+            .map(|bucket| (bucket.start.to_rfc3339(), bucket.stats.requests))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("2026-10-10T13:55:00+02:00".to_owned(), 1),
+                ("2026-10-10T14:00:00+02:00".to_owned(), 2),
+                ("2026-10-10T14:20:00+02:00".to_owned(), 1),
+            ]
+        );
+        assert_eq!(buckets[1].stats.error_rate, 0.5);
+        assert_eq!(buckets[1].requests_per_second, 2.0 / 300.0);
+        assert_eq!(buckets[1].end.to_rfc3339(), "2026-10-10T14:05:00+02:00");
+    }
+
+    #[test]
+    fn filter_counts_only_matching_lines() {
+        let filter = concat!(
+            r#"status >= 500 && path ~ "^/api/" && "#,
+            "(ip in 10.0.0.0/8 || upstream_time >= 1) && !(agent contains bot)"
+        );
+        let mut builder = LogStatsBuilder::new().filter(filter.parse().unwrap());
+        let from = |ip: &str, line: NginxLogLine| NginxLogLine {
+            remote_ip: ip.to_owned(),
+            request: line.request.replace(" / ", " /api/slow "),
+            ..line
+        };
+        builder.add(&from("10.1.2.3", line("GET /api/a HTTP/1.1", 502, 1)));
+        builder.add(&from("10.1.2.3", line("GET /api/b HTTP/1.1", 200, 2)));
+        builder.add(&from("10.1.2.3", line("GET /web HTTP/1.1", 503, 4)));
+        builder.add(&from("192.168.0.1", line("GET /api/c HTTP/1.1", 500, 8)));
+        builder.add(&from("192.168.0.1", timed(504, "2", "1.5")));
+        builder.add(&from("192.168.0.1", timed(504, "2", "0.5")));
+        let stats = builder.build();
+
+        assert_eq!(stats.endpoint_count.len(), 2);
+        assert_eq!(stats.endpoint_count["/api/a"], 1);
+        assert_eq!(stats.endpoint_count["/api/slow"], 1);
+        assert_eq!(stats.status_count[&504], 1);
+    }
+
+    #[test]
+    fn top_clients_find_heavy_hitters() {
+        let ips = ["a", "b", "a", "c", "a", "b", "d", "a", "e", "b", "a", "f"];
+        let lines = ips.map(|ip| NginxLogLine {
+            remote_ip: ip.to_owned(),
+            ..line("GET / HTTP/1.1", if ip == "b" { 500 } else { 200 }, 10)
+        });
+        let top_ips = |builder: LogStatsBuilder| builder.build().clients.unwrap().remote_ip;
+
+        let mut exact = LogStatsBuilder::new().top_clients(2, None);
+        let mut approximate = LogStatsBuilder::new().top_clients(2, Some(3));
+        let mut merged = approximate.new_like();
+        for line in &lines {
+            exact.add(line);
+            approximate.add(line);
+        }
+        for half in lines.chunks(6) {
+            let mut builder = merged.new_like();
+            half.iter().for_each(|line| builder.add(line));
+            merged.merge(builder);
+        }
+
+        let exact = top_ips(exact);
+        let value = |value: &str, count, error| TopValue {
+            value: value.to_owned(),
+            count,
+            error,
+        };
+        assert_eq!(exact.requests, [value("a", 5, 0), value("b", 3, 0)]);
+        assert_eq!(exact.bytes, [value("a", 50, 0), value("b", 30, 0)]);
+        assert_eq!(exact.errors, [value("b", 3, 0)]);
+
+        for top in [top_ips(approximate), top_ips(merged)] {
+            assert_eq!(top.requests[0].value, "a");
+            assert_eq!(top.bytes[0].value, "a");
+            assert_eq!(top.errors, exact.errors);
+            for value in &top.requests {
+                let true_count = ips.iter().filter(|ip| **ip == value.value).count() as u64;
+                assert!(value.count >= true_count && value.count - value.error <= true_count);
+            }
+        }
+    }
+}
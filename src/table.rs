@@ -0,0 +1,119 @@
+/// Text tables shared by the endpoint, time bucket and top client tables
+use std::collections::BTreeSet;
+
+use crate::stats::RequestStats;
+
+/// The columns of a table of [`RequestStats`], chosen from all of its rows
+///
+/// Status classes are shown if any row has responses in them, and timing columns if any row has
+/// the timing.
+pub(crate) struct Columns {
+    classes: BTreeSet<String>,
+    percentiles: Vec<f64>,
+    request_time: bool,
+    upstream_response_time: bool,
+}
+
+impl Columns {
+    pub(crate) fn of<'a>(rows: impl Iterator<Item = &'a RequestStats> + Clone) -> Self {
+        Self {
+            classes: rows
+                .clone()
+                .flat_map(|stats| stats.status_classes.keys().cloned())
+                .collect(),
+            percentiles: rows
+                .clone()
+                .next()
+                .map(|stats| stats.bytes.percentiles.iter())
+                .into_iter()
+                .flatten()
+                .map(|p| p.percentile)
+                .collect(),
+            request_time: rows.clone().any(|stats| stats.request_time.is_some()),
+            upstream_response_time: rows.clone().any(|s| s.upstream_response_time.is_some()),
+        }
+    }
+
+    pub(crate) fn header(&self) -> Vec<String> {
+        let mut header = vec!["Requests".to_owned(), "Errors".to_owned()];
+        header.push("Error %".to_owned());
+        header.extend(self.classes.iter().cloned());
+        for (name, shown) in [
+            ("Bytes", true),
+            ("Time", self.request_time),
+            ("Upstream", self.upstream_response_time),
+        ] {
+            if shown {
+                header.extend(self.percentiles.iter().map(|p| format!("{} p{}", name, p)));
+            }
+        }
+        header
+    }
+
+    pub(crate) fn cells(&self, stats: &RequestStats) -> Vec<String> {
+        let mut row = vec![
+            stats.requests.to_string(),
+            stats.errors.to_string(),
+            format!("{:.1}", stats.error_rate * 100.0),
+        ];
+        row.extend(self.classes.iter().map(|class| {
+            let count = stats.status_classes.get(class).copied().unwrap_or(0);
+            count.to_string()
+        }));
+
+        for (summary, shown, decimals) in [
+            (Some(&stats.bytes), true, 0),
+            (stats.request_time.as_ref(), self.request_time, 3),
+            (
+                stats.upstream_response_time.as_ref(),
+                self.upstream_response_time,
+                3,
+            ),
+        ] {
+            if shown {
+                row.extend(
+                    self.percentiles
+                        .iter()
+                        .map(|p| format_value(summary.and_then(|s| s.percentile(*p)), decimals)),
+                );
+            }
+        }
+        row
+    }
+}
+
+/// Writes rows of cells as an indented table, with the first column aligned left and the rest
+/// aligned right
+pub(crate) fn write(f: &mut std::fmt::Formatter<'_>, rows: &[Vec<String>]) -> std::fmt::Result {
+    let columns = rows.first().map(Vec::len).unwrap_or(0);
+    let widths = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    for row in rows {
+        write!(f, " ")?;
+        for (column, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            if column == 0 {
+                write!(f, " {:<width$}", cell)?;
+            } else {
+                write!(f, "  {:>width$}", cell)?;
+            }
+        }
+        writeln!(f)?;
+    }
+
+    Ok(())
+}
+
+/// Formats a table cell with the given number of decimals, or `n/a` if it is missing
+fn format_value(value: Option<f64>, decimals: usize) -> String {
+    match value {
+        Some(value) => format!("{:.*}", decimals, value),
+        None => "n/a".to_owned(),
+    }
+}
@@ -0,0 +1,363 @@
+/// Filtering of log lines to a window of time
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use std::fmt::Display;
+use std::io::{BufRead, Seek, SeekFrom};
+
+use crate::nginx_log::LogFormat;
+use crate::timestamp;
+
+/// A window of time that log lines are kept from
+///
+/// Lines logged at or after `since` and before `until` are in the range, and either bound can be
+/// left open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    pub since: Option<DateTime<FixedOffset>>,
+    pub until: Option<DateTime<FixedOffset>>,
+}
+
+impl TimeRange {
+    /// Creates a new TimeRange
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - The earliest time in the range, or `None` for no lower bound
+    /// * `until` - The end of the range, exclusive, or `None` for no upper bound
+    pub fn new(since: Option<DateTime<FixedOffset>>, until: Option<DateTime<FixedOffset>>) -> Self {
+        Self { since, until }
+    }
+
+    /// Returns whether neither bound is set, so that every line is in the range
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Returns whether a time is in the range
+    pub fn contains(&self, time: &DateTime<FixedOffset>) -> bool {
+        self.since.is_none_or(|since| *time >= since)
+            && self.until.is_none_or(|until| *time < until)
+    }
+}
+
+/// Error returned when a time given on the command line cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidTime {
+    pub value: String,
+}
+
+impl Display for InvalidTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid time {:?}, expected a time such as 2026-10-10T13:55:00+02:00, \
+             \"2026-10-10 13:55\", 13:55 or \"2h ago\"",
+            self.value
+        )
+    }
+}
+
+impl std::error::Error for InvalidTime {}
+
+/// Units of relative times, with the names they can be written with
+const UNITS: [(&[&str], i64); 5] = [
+    (&["s", "sec", "secs", "second", "seconds"], 1),
+    (&["m", "min", "mins", "minute", "minutes"], 60),
+    (&["h", "hour", "hours"], 3600),
+    (&["d", "day", "days"], 86400),
+    (&["w", "week", "weeks"], 604800),
+];
+
+/// Formats of local times without a time zone, tried in order
+const LOCAL_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+/// Parses a time given on the command line
+///
+/// Accepts:
+///
+/// * Any time nginx writes, see [`timestamp::parse`], such as `2026-10-10T13:55:00+02:00`
+/// * A local time without a time zone, like `2026-10-10 13:55:00`, `2026-10-10 13:55` or
+///   `2026-10-10` for midnight
+/// * A time of day today, like `13:55` or `13:55:00`
+/// * `now`, or a time relative to it like `2h ago`, `90 minutes ago` or `1d ago`
+///
+/// # Arguments
+///
+/// * `value` - The time to parse
+/// * `now` - The current time, which relative times and times of day are based on
+///
+/// # Errors
+///
+/// Returns an error if the value is not in any of these formats
+pub fn parse_time(value: &str, now: DateTime<Local>) -> Result<DateTime<FixedOffset>, InvalidTime> {
+    let value = value.trim();
+    let error = || InvalidTime {
+        value: value.to_owned(),
+    };
+
+    if value == "now" {
+        return Ok(now.fixed_offset());
+    }
+    if let Some(ago) = value.strip_suffix("ago") {
+        return parse_ago(ago.trim())
+            .and_then(|ago| now.checked_sub_signed(ago))
+            .map(|time| time.fixed_offset())
+            .ok_or_else(error);
+    }
+    if let Ok(time) = timestamp::parse(value) {
+        return Ok(time);
+    }
+
+    let naive = LOCAL_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+            Some(date.and_time(NaiveTime::MIN))
+        })
+        .or_else(|| {
+            let time = NaiveTime::parse_from_str(value, "%H:%M:%S")
+                .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M"))
+                .ok()?;
+            Some(now.date_naive().and_time(time))
+        })
+        .ok_or_else(error)?;
+    naive
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|time| time.fixed_offset())
+        .ok_or_else(error)
+}
+
+/// Parses the amount of time before `ago`, like `2h` or `90 minutes`
+fn parse_ago(ago: &str) -> Option<Duration> {
+    let digits = ago.bytes().take_while(u8::is_ascii_digit).count();
+    let count = ago[..digits].parse::<i64>().ok()?;
+    let unit = ago[digits..].trim_start();
+    let (_, seconds) = UNITS.iter().find(|(names, _)| names.contains(&unit))?;
+    Duration::try_seconds(count.checked_mul(*seconds)?)
+}
+
+/// Finds where the lines logged at or after a time start in a log sorted by time
+///
+/// Binary searches the log for the first line logged at or after `since`, reading only a few
+/// lines at each step, so that the start of a time range can be found in a large log without
+/// reading everything before it. Lines whose time cannot be parsed are skipped over when
+/// comparing, so the offset may be that of such lines just before the first line at or after
+/// `since`. They are outside the window all the same, see [`NginxLogReader::time_range`].
+///
+/// [`NginxLogReader::time_range`]: crate::nginx_log::NginxLogReader::time_range
+///
+/// # Arguments
+///
+/// * `reader` - The uncompressed log, which is left at an unspecified position
+/// * `format` - The format of the log
+/// * `since` - The time to find
+///
+/// # Returns
+///
+/// The byte offset of the first line logged at or after `since`, or the length of the log if
+/// there is none
+///
+/// # Errors
+///
+/// Returns an error if the log cannot be read or seeked
+pub fn seek_since<R: BufRead + Seek>(
+    reader: &mut R,
+    format: &LogFormat,
+    since: &DateTime<FixedOffset>,
+) -> std::io::Result<u64> {
+    let mut line = Vec::new();
+    let mut read_line = |reader: &mut R| -> std::io::Result<Option<(usize, String)>> {
+        line.clear();
+        match reader.read_until(b'\n', &mut line)? {
+            0 => Ok(None),
+            read => {
+                let text = String::from_utf8_lossy(&line);
+                Ok(Some((read, text.trim_end_matches(['\n', '\r']).to_owned())))
+            }
+        }
+    };
+
+    // Every line starting before `low` was logged before `since`, and the line at `high`, if
+    // any, at or after it. Both are always at the start of a line
+    let mut low = 0;
+    let mut high = reader.seek(SeekFrom::End(0))?;
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let mut start = middle;
+        if middle > 0 {
+            reader.seek(SeekFrom::Start(middle - 1))?;
+            start = middle - 1 + read_line(reader)?.map_or(0, |(read, _)| read as u64);
+        }
+        // No line starts between the middle and `high`, so compare the line at `low` instead
+        if start >= high {
+            start = low;
+        }
+        reader.seek(SeekFrom::Start(start))?;
+
+        let mut end = start;
+        let time = loop {
+            let Some((read, text)) = read_line(reader)? else {
+                break None;
+            };
+            end += read as u64;
+            if let Some(time) = format.parse_time(&text) {
+                break Some(time);
+            }
+        };
+
+        match time {
+            Some(time) if time < *since => low = end,
+            _ => high = start,
+        }
+    }
+
+    Ok(low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+    use std::io::Cursor;
+
+    fn line(minute: u32) -> String {
+        format!(
+            "10.0.0.1 - - [10/Oct/2026:13:{:02}:00 +0000] \"GET / HTTP/1.1\" 200 0\n",
+            minute
+        )
+    }
+
+    fn at(minute: u32) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(&format!("2026-10-10T13:{:02}:00+00:00", minute)).unwrap()
+    }
+
+    fn seek(log: &str, minute: u32) -> u64 {
+        seek_since(&mut Cursor::new(log), &LogFormat::Combined, &at(minute)).unwrap()
+    }
+
+    #[test]
+    fn finds_the_first_line_at_or_after_since() {
+        assert_eq!(seek("", 0), 0);
+
+        let minutes = (0..10).map(|i| i * 2);
+        let log = minutes.clone().map(line).collect::<String>();
+        for minute in 0..=20 {
+            let before = minutes.clone().filter(|logged| *logged < minute);
+            let expected = before.map(|logged| line(logged).len() as u64).sum::<u64>();
+            assert_eq!(seek(&log, minute), expected, "since minute {}", minute);
+        }
+        assert_eq!(seek(&log, 0), 0);
+        assert_eq!(seek(&log, 30), log.len() as u64);
+    }
+
+    #[test]
+    fn handles_line_endings() {
+        let crlf = [line(1), line(2), line(3)].concat().replace('\n', "\r\n");
+        assert_eq!(seek(&crlf, 2), line(1).len() as u64 + 1);
+        assert_eq!(seek(&crlf, 4), crlf.len() as u64);
+
+        let unterminated = [line(1), line(2), line(3)].concat();
+        let unterminated = unterminated.trim_end();
+        assert_eq!(seek(unterminated, 3), 2 * line(1).len() as u64);
+        assert_eq!(seek(unterminated, 4), unterminated.len() as u64);
+    }
+
+    #[test]
+    fn skips_over_unparseable_lines() {
+        let garbage = "garbage\n".repeat(6);
+        let log = [line(1), line(2), garbage.clone(), line(5), line(6)].concat();
+        let first_garbage = 2 * line(1).len() as u64;
+        let after_garbage = first_garbage + garbage.len() as u64;
+
+        for minute in 3..=5 {
+            let offset = seek(&log, minute);
+            assert!(
+                (first_garbage..=after_garbage).contains(&offset),
+                "since minute {}: {}",
+                minute,
+                offset
+            );
+            assert!(log[..offset as usize].ends_with('\n'));
+        }
+        assert_eq!(seek(&log, 6), after_garbage + line(5).len() as u64);
+
+        let garbage = "garbage\n".repeat(5);
+        assert_eq!(seek(&garbage, 0), 0);
+    }
+
+    fn local(date: (i32, u32, u32), time: (u32, u32, u32)) -> DateTime<FixedOffset> {
+        NaiveDate::from_ymd_opt(date.0, date.1, date.2)
+            .and_then(|date| date.and_hms_opt(time.0, time.1, time.2))
+            .and_then(|time| time.and_local_timezone(Local).earliest())
+            .unwrap()
+            .fixed_offset()
+    }
+
+    #[test]
+    fn parses_absolute_and_local_times() {
+        let now = Local.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+        let parse = |value| parse_time(value, now);
+
+        assert_eq!(
+            parse("2026-10-10T13:55:00+02:00").unwrap().to_rfc3339(),
+            "2026-10-10T13:55:00+02:00"
+        );
+        assert_eq!(
+            parse("10/Oct/2026:13:55:36 -0700").unwrap().to_rfc3339(),
+            "2026-10-10T13:55:36-07:00"
+        );
+
+        let expected = local((2026, 10, 10), (13, 55, 30));
+        assert_eq!(parse("2026-10-10 13:55:30"), Ok(expected));
+        assert_eq!(parse(" 2026-10-10T13:55:30 "), Ok(expected));
+        let expected = local((2026, 10, 10), (13, 55, 0));
+        assert_eq!(parse("2026-10-10 13:55"), Ok(expected));
+        assert_eq!(parse("2026-10-10T13:55"), Ok(expected));
+        assert_eq!(parse("2026-10-10"), Ok(local((2026, 10, 10), (0, 0, 0))));
+        assert_eq!(parse("13:55"), Ok(local((2026, 10, 16), (13, 55, 0))));
+        assert_eq!(parse("13:55:30"), Ok(local((2026, 10, 16), (13, 55, 30))));
+
+        for invalid in ["", "yesterday", "2026-13-01", "25:00", "2026-10-10 13"] {
+            assert_eq!(
+                parse(invalid),
+                Err(InvalidTime {
+                    value: invalid.to_owned()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn parses_relative_times() {
+        let now = Local.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+        let ago = |value| parse_time(value, now).map(|time| now.fixed_offset() - time);
+
+        assert_eq!(ago("now"), Ok(Duration::zero()));
+        assert_eq!(ago("2h ago"), Ok(Duration::hours(2)));
+        assert_eq!(ago("90 minutes ago"), Ok(Duration::minutes(90)));
+        assert_eq!(ago("30 s ago"), Ok(Duration::seconds(30)));
+        assert_eq!(ago("1d ago"), Ok(Duration::days(1)));
+        assert_eq!(ago(" 3 weeks ago "), Ok(Duration::weeks(3)));
+        assert_eq!(ago("0m ago"), Ok(Duration::zero()));
+
+        for invalid in [
+            "ago",
+            "h ago",
+            "2 ago",
+            "-2h ago",
+            "2 fortnights ago",
+            "1.5h ago",
+            "2h",
+            "99999999999999999 weeks ago",
+            "9999999999999 weeks ago",
+        ] {
+            assert!(ago(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+/// Parsing of the time formats nginx can write to its logs
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use serde::de::{self, Visitor};
+use serde::{Deserializer, Serializer};
+use std::fmt::Display;
+
+/// The `strftime` format of nginx's `$time_local`
+pub const TIME_LOCAL_FORMAT: &str = "%d/%b/%Y:%H:%M:%S %z";
+
+/// Error returned when a value is not in any of the time formats nginx writes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeParseError {
+    pub value: String,
+}
+
+impl Display for TimeParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid time {:?}, expected $time_local, $time_iso8601 or $msec",
+            self.value
+        )
+    }
+}
+
+impl std::error::Error for TimeParseError {}
+
+/// Parses a time written by nginx
+///
+/// Accepts `$time_local` (`10/Oct/2026:13:55:36 +0000`), `$time_iso8601`
+/// (`2026-10-10T13:55:36+00:00`) and `$msec` (`1791640536.123`, seconds since the epoch). `$msec`
+/// times are returned in UTC.
+///
+/// # Arguments
+///
+/// * `value` - The time as written in the log
+///
+/// # Errors
+///
+/// Returns an error if the value is not in any of these formats
+pub fn parse(value: &str) -> Result<DateTime<FixedOffset>, TimeParseError> {
+    let error = || TimeParseError {
+        value: value.to_owned(),
+    };
+
+    if value.contains('/') {
+        DateTime::parse_from_str(value, TIME_LOCAL_FORMAT).map_err(|_| error())
+    } else if value.contains('-') {
+        DateTime::parse_from_rfc3339(value).map_err(|_| error())
+    } else {
+        parse_msec(value).ok_or_else(error)
+    }
+}
+
+/// Parses `$msec`, seconds since the epoch with an optional fraction
+fn parse_msec(value: &str) -> Option<DateTime<FixedOffset>> {
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if seconds.is_empty() || fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let seconds = seconds.parse::<i64>().ok()?;
+    let nanos = format!("{:0<9}", fraction).parse::<u32>().ok()?;
+    Some(Utc.timestamp_opt(seconds, nanos).single()?.fixed_offset())
+}
+
+/// Formats a time as nginx's `$time_local`
+pub fn format_local(time: &DateTime<FixedOffset>) -> String {
+    time.format(TIME_LOCAL_FORMAT).to_string()
+}
+
+/// Serializes a time as `$time_iso8601`, which [`deserialize`] reads back
+pub fn serialize<S: Serializer>(
+    time: &DateTime<FixedOffset>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&time.to_rfc3339())
+}
+
+/// Deserializes a time from a JSON string, or from a JSON number holding `$msec`
+pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<FixedOffset>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct TimeVisitor;
+
+    impl Visitor<'_> for TimeVisitor {
+        type Value = DateTime<FixedOffset>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("an nginx $time_local, $time_iso8601 or $msec time")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            parse(value).map_err(E::custom)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+            self.visit_str(&value.to_string())
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+            self.visit_str(&value.to_string())
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+            self.visit_str(&format!("{:.3}", value))
+        }
+    }
+
+    deserializer.deserialize_any(TimeVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_msec_with_any_fraction() {
+        let whole = parse("1791640536").unwrap();
+        assert_eq!(whole.to_rfc3339(), "2026-10-10T13:55:36+00:00");
+        let nanos = parse("1791640536.123456789").unwrap();
+        assert_eq!(nanos.timestamp_subsec_nanos(), 123_456_789);
+        assert_eq!(
+            parse("1791640536.5").unwrap().timestamp_subsec_millis(),
+            500
+        );
+
+        for invalid in ["1791640536.1234567890", ".5", "1791640536.-5", "soon"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn time_local_matches_time_iso8601() {
+        let local = parse("10/Oct/2026:13:55:36 +0200").unwrap();
+        let iso8601 = parse("2026-10-10T13:55:36+02:00").unwrap();
+        assert_eq!(local, iso8601);
+        assert_eq!(local.offset(), iso8601.offset());
+        assert_eq!(local, parse("1791633336").unwrap());
+        assert_eq!(format_local(&iso8601), "10/Oct/2026:13:55:36 +0200");
+    }
+
+    #[test]
+    fn deserializes_strings_and_numbers() {
+        let time = |json: &str| {
+            let mut deserializer = serde_json::Deserializer::from_str(json);
+            deserialize(&mut deserializer).unwrap().to_rfc3339()
+        };
+        assert_eq!(
+            time(r#""2026-10-10T13:55:36+02:00""#),
+            "2026-10-10T13:55:36+02:00"
+        );
+        assert_eq!(time("1791640536"), "2026-10-10T13:55:36+00:00");
+        assert_eq!(time("1791640536.25"), "2026-10-10T13:55:36.250+00:00");
+    }
+}
@@ -0,0 +1,646 @@
+/// Top-N reports of the client IPs, user agents and referrers with the most requests
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt::Display;
+use std::sync::Arc;
+
+use crate::table;
+
+/// A summary of the values with the largest total weight, in bounded memory
+///
+/// Implements the Space-Saving algorithm: at most `capacity` values are counted, and when a new
+/// value is seen while the summary is full, the value with the smallest count is replaced and
+/// the new value inherits its count. Counts can then only overestimate, by at most the count
+/// inherited, which is kept as the error of each value. Every value whose true total is more
+/// than the sum of all weights divided by `capacity` is guaranteed to be in the summary.
+#[derive(Debug, Clone)]
+pub struct SpaceSaving {
+    capacity: usize,
+    /// The count and maximum overestimate of each value
+    counters: HashMap<Arc<str>, (u64, u64)>,
+    /// Every value with its count when it was last looked at as the smallest, which is no larger
+    /// than its count, so the smallest is found without reordering on every increment
+    smallest: BinaryHeap<Reverse<(u64, Arc<str>)>>,
+}
+
+impl SpaceSaving {
+    /// Creates a new empty SpaceSaving summary
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of values to count
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be at least 1");
+        Self {
+            capacity,
+            counters: HashMap::new(),
+            smallest: BinaryHeap::new(),
+        }
+    }
+
+    /// Creates a new SpaceSaving summary from exact counts
+    ///
+    /// Only the `capacity` values with the largest counts are kept, with no error.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of values to count
+    /// * `counts` - The values and their counts
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0
+    pub fn from_counts(capacity: usize, counts: impl IntoIterator<Item = (String, u64)>) -> Self {
+        let counters = counts
+            .into_iter()
+            .map(|(value, count)| (Arc::from(value), (count, 0)))
+            .collect();
+        let mut summary = Self::new(capacity);
+        summary.replace(counters);
+        summary
+    }
+
+    /// Returns the number of values counted
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Adds a weight to the count of a value
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value seen
+    /// * `weight` - The weight to add, values with a weight of 0 are ignored
+    pub fn add(&mut self, value: &str, weight: u64) {
+        if weight == 0 {
+            return;
+        }
+
+        if let Some((count, _)) = self.counters.get_mut(value) {
+            *count = count.saturating_add(weight);
+            return;
+        }
+
+        let (count, error) = if self.counters.len() < self.capacity {
+            (weight, 0)
+        } else {
+            let (min, evicted) = self.pop_smallest();
+            self.counters.remove(&evicted);
+            (min.saturating_add(weight), min)
+        };
+        let key = Arc::<str>::from(value);
+        self.smallest.push(Reverse((count, key.clone())));
+        self.counters.insert(key, (count, error));
+    }
+
+    /// Adds the counts of another summary to this one
+    ///
+    /// A value missing from a full summary may still have been seen up to its smallest count
+    /// times, so that count is added to both the value's count and its error. The values with
+    /// the largest merged counts are kept, up to the capacity of this summary.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The summary to merge into this one
+    pub fn merge(&mut self, other: &SpaceSaving) {
+        let (own_bound, other_bound) = (self.missing_bound(), other.missing_bound());
+
+        let mut counters = HashMap::with_capacity(self.counters.len() + other.counters.len());
+        for (key, &(count, error)) in &self.counters {
+            let (other_count, other_error) = other
+                .counters
+                .get(key)
+                .copied()
+                .unwrap_or((other_bound, other_bound));
+            counters.insert(
+                key.clone(),
+                (
+                    count.saturating_add(other_count),
+                    error.saturating_add(other_error),
+                ),
+            );
+        }
+        for (key, &(count, error)) in &other.counters {
+            if !self.counters.contains_key(key) {
+                counters.insert(
+                    key.clone(),
+                    (
+                        count.saturating_add(own_bound),
+                        error.saturating_add(own_bound),
+                    ),
+                );
+            }
+        }
+        self.replace(counters);
+    }
+
+    /// Returns the values with the largest counts, largest first with ties broken alphabetically
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of values to return
+    pub fn top(&self, n: usize) -> Vec<TopValue> {
+        let values = self
+            .counters
+            .iter()
+            .map(|(value, &(count, error))| TopValue {
+                value: value.to_string(),
+                count,
+                error,
+            });
+        largest(values, n)
+    }
+
+    /// Removes the value with the smallest count from the heap, returning its count and value
+    fn pop_smallest(&mut self) -> (u64, Arc<str>) {
+        loop {
+            let Reverse((seen, key)) = self.smallest.pop().expect("a full summary counts values");
+            let (count, _) = self.counters[&key];
+            if count == seen {
+                return (count, key);
+            }
+            self.smallest.push(Reverse((count, key)));
+        }
+    }
+
+    /// The most a value missing from the summary can have been seen
+    fn missing_bound(&self) -> u64 {
+        if self.counters.len() < self.capacity {
+            0
+        } else {
+            self.counters
+                .values()
+                .map(|(count, _)| *count)
+                .min()
+                .unwrap_or(0)
+        }
+    }
+
+    /// Replaces the counted values with the largest of the given counters
+    fn replace(&mut self, counters: HashMap<Arc<str>, (u64, u64)>) {
+        let mut counters = counters.into_iter().collect::<Vec<_>>();
+        if counters.len() > self.capacity {
+            counters.sort_by(|(a, (a_count, _)), (b, (b_count, _))| {
+                b_count.cmp(a_count).then_with(|| a.cmp(b))
+            });
+            counters.truncate(self.capacity);
+        }
+
+        self.smallest = counters
+            .iter()
+            .map(|(key, (count, _))| Reverse((*count, key.clone())))
+            .collect();
+        self.counters = counters.into_iter().collect();
+    }
+}
+
+/// Counts values exactly until there are too many distinct ones, then with [`SpaceSaving`]
+#[derive(Debug, Clone)]
+pub struct TopCounter {
+    counts: Counts,
+    /// The number of distinct values counted exactly, or `None` to always count exactly
+    capacity: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum Counts {
+    Exact(HashMap<String, u64>),
+    Approximate(SpaceSaving),
+}
+
+impl TopCounter {
+    /// The number of distinct values counted unless another capacity is chosen
+    pub const DEFAULT_CAPACITY: usize = 10_000;
+
+    /// Creates a new empty TopCounter
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of distinct values to count exactly before switching to a
+    ///   [`SpaceSaving`] summary of that many values, or `None` to always count exactly
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0
+    pub fn new(capacity: Option<usize>) -> Self {
+        assert!(capacity != Some(0), "capacity must be at least 1");
+        Self {
+            counts: Counts::Exact(HashMap::new()),
+            capacity,
+        }
+    }
+
+    /// Creates a new empty TopCounter with the same capacity as this one
+    pub fn new_like(&self) -> Self {
+        Self::new(self.capacity)
+    }
+
+    /// Adds a weight to the count of a value
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value seen
+    /// * `weight` - The weight to add, values with a weight of 0 are ignored
+    pub fn add(&mut self, value: &str, weight: u64) {
+        if weight == 0 {
+            return;
+        }
+
+        match &mut self.counts {
+            Counts::Exact(counts) => {
+                if let Some(count) = counts.get_mut(value) {
+                    *count = count.saturating_add(weight);
+                } else {
+                    counts.insert(value.to_owned(), weight);
+                    self.promote_if_full();
+                }
+            }
+            Counts::Approximate(summary) => summary.add(value, weight),
+        }
+    }
+
+    /// Adds the counts of another counter to this one
+    ///
+    /// The merged counts are approximate if either counter's are.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The counter to merge into this one
+    pub fn merge(&mut self, other: TopCounter) {
+        match (&mut self.counts, other.counts) {
+            (Counts::Exact(counts), Counts::Exact(other)) => {
+                for (value, count) in other {
+                    let total = counts.entry(value).or_insert(0);
+                    *total = total.saturating_add(count);
+                }
+                self.promote_if_full();
+            }
+            (Counts::Approximate(summary), Counts::Exact(other)) => {
+                // Adding each count in a fixed order keeps merging deterministic, and is cheaper
+                // than merging summaries when there are only a few values
+                let mut other = other.into_iter().collect::<Vec<_>>();
+                other.sort_unstable();
+                for (value, count) in other {
+                    summary.add(&value, count);
+                }
+            }
+            (Counts::Exact(counts), Counts::Approximate(other)) => {
+                let counts = std::mem::take(counts);
+                let mut summary = SpaceSaving::from_counts(other.capacity(), counts);
+                summary.merge(&other);
+                self.counts = Counts::Approximate(summary);
+            }
+            (Counts::Approximate(summary), Counts::Approximate(other)) => summary.merge(&other),
+        }
+    }
+
+    /// Returns the values with the largest counts, largest first with ties broken alphabetically
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of values to return
+    pub fn top(&self, n: usize) -> Vec<TopValue> {
+        match &self.counts {
+            Counts::Exact(counts) => {
+                let values = counts.iter().map(|(value, &count)| TopValue {
+                    value: value.clone(),
+                    count,
+                    error: 0,
+                });
+                largest(values, n)
+            }
+            Counts::Approximate(summary) => summary.top(n),
+        }
+    }
+
+    /// Switches to a [`SpaceSaving`] summary once there are more values than the capacity
+    fn promote_if_full(&mut self) {
+        if let (Counts::Exact(counts), Some(capacity)) = (&mut self.counts, self.capacity) {
+            if counts.len() > capacity {
+                let counts = std::mem::take(counts);
+                self.counts = Counts::Approximate(SpaceSaving::from_counts(capacity, counts));
+            }
+        }
+    }
+}
+
+/// Returns the `n` values with the largest counts, largest first with ties broken alphabetically
+fn largest(values: impl Iterator<Item = TopValue>, n: usize) -> Vec<TopValue> {
+    let mut values = values.collect::<Vec<_>>();
+    values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    values.truncate(n);
+    values
+}
+
+/// A value and how often it was seen
+///
+/// # Contains
+///
+/// * `value` - The value
+/// * `count` - Its number of requests, bytes or errors, which can be overestimated once the
+///   values are counted approximately, and stops at `u64::MAX`
+/// * `error` - The most `count` can be overestimated by, 0 if it is exact
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TopValue {
+    pub value: String,
+    pub count: u64,
+    pub error: u64,
+}
+
+/// The values of one field with the most requests, bytes and errors
+///
+/// # Contains
+///
+/// * `requests` - The values with the most requests
+/// * `bytes` - The values with the most bytes returned
+/// * `errors` - The values with the most failed requests
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TopValues {
+    pub requests: Vec<TopValue>,
+    pub bytes: Vec<TopValue>,
+    pub errors: Vec<TopValue>,
+}
+
+/// The client IPs, user agents and referrers with the most requests, bytes and errors
+///
+/// Requests without a user agent or referrer, logged as `-`, are left out of those fields.
+///
+/// # Contains
+///
+/// * `remote_ip` - The top client IPs
+/// * `agent` - The top user agents
+/// * `referrer` - The top referrers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TopClients {
+    pub remote_ip: TopValues,
+    pub agent: TopValues,
+    pub referrer: TopValues,
+}
+
+/// Text tables of the top clients, one per field and count with any values
+pub struct TopClientsTable<'a>(pub &'a TopClients);
+
+impl Display for TopClientsTable<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, column, values) in [
+            ("Client IPs", "Client IP", &self.0.remote_ip),
+            ("User Agents", "User Agent", &self.0.agent),
+            ("Referrers", "Referrer", &self.0.referrer),
+        ] {
+            for (count, top) in [
+                ("Requests", &values.requests),
+                ("Bytes", &values.bytes),
+                ("Errors", &values.errors),
+            ] {
+                if top.is_empty() {
+                    continue;
+                }
+
+                let approximate = top.iter().any(|value| value.error > 0);
+                let mut header = vec![column.to_owned(), count.to_owned()];
+                if approximate {
+                    header.push("Max Overcount".to_owned());
+                }
+                let mut rows = vec![header];
+                for value in top {
+                    let mut row = vec![value.value.clone(), value.count.to_string()];
+                    if approximate {
+                        row.push(value.error.to_string());
+                    }
+                    rows.push(row);
+                }
+
+                writeln!(f, "Top {} by {}:", name, count)?;
+                table::write(f, &rows)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A skewed stream of weighted values, with a few heavy hitters among many rare values
+    fn stream(len: usize, seed: u64) -> Vec<(String, u64)> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let random = state >> 33;
+                let value = match random % 100 {
+                    0..30 => 0,
+                    30..50 => 1,
+                    50..60 => 2,
+                    other => 3 + (random / 100 + other) % 97,
+                };
+                (format!("v{}", value), 1 + random % 5)
+            })
+            .collect()
+    }
+
+    fn totals(stream: &[(String, u64)]) -> HashMap<String, u64> {
+        let mut totals = HashMap::new();
+        for (value, weight) in stream {
+            *totals.entry(value.clone()).or_insert(0) += weight;
+        }
+        totals
+    }
+
+    fn value(value: &str, count: u64, error: u64) -> TopValue {
+        TopValue {
+            value: value.to_owned(),
+            count,
+            error,
+        }
+    }
+
+    /// Checks that every count is within its error of the true total, and that every value
+    /// heavier than `total / capacity` is found
+    fn assert_bounded(top: &[TopValue], totals: &HashMap<String, u64>, capacity: usize) {
+        let total = totals.values().sum::<u64>();
+        for value in top {
+            let actual = totals.get(&value.value).copied().unwrap_or(0);
+            assert!(value.count >= actual, "{:?} undercounts {}", value, actual);
+            assert!(
+                value.count - value.error <= actual,
+                "{:?} > {}",
+                value,
+                actual
+            );
+        }
+        for (heavy, _) in totals.iter().filter(|(_, t)| **t > total / capacity as u64) {
+            assert!(top.iter().any(|value| value.value == *heavy), "{}", heavy);
+        }
+    }
+
+    #[test]
+    fn evicts_the_smallest_count() {
+        let mut summary = SpaceSaving::new(2);
+        summary.add("a", 3);
+        summary.add("b", 1);
+        summary.add("c", 1);
+        assert_eq!(summary.top(10), [value("a", 3, 0), value("c", 2, 1)]);
+
+        summary.add("b", 5);
+        assert_eq!(summary.top(10), [value("b", 7, 2), value("a", 3, 0)]);
+        summary.add("a", 5);
+        summary.add("d", 1);
+        assert_eq!(summary.top(10), [value("a", 8, 0), value("d", 8, 7)]);
+    }
+
+    #[test]
+    fn counts_are_within_their_error() {
+        let stream = stream(20_000, 1);
+        let totals = totals(&stream);
+        let total = totals.values().sum::<u64>();
+
+        for capacity in [1, 5, 10, 50] {
+            let mut summary = SpaceSaving::new(capacity);
+            stream
+                .iter()
+                .for_each(|(value, weight)| summary.add(value, *weight));
+            let top = summary.top(capacity);
+            assert_eq!(top.len(), capacity);
+            assert_bounded(&top, &totals, capacity);
+            assert!(top
+                .iter()
+                .all(|value| value.error <= total / capacity as u64));
+        }
+
+        let mut summary = SpaceSaving::new(200);
+        stream
+            .iter()
+            .for_each(|(value, weight)| summary.add(value, *weight));
+        assert!(summary.top(200).iter().all(|value| value.error == 0));
+        assert_eq!(summary.top(1), [value("v0", totals["v0"], 0)]);
+    }
+
+    #[test]
+    fn ties_are_broken_alphabetically() {
+        let mut counter = TopCounter::new(None);
+        for value in ["d", "b", "c", "a"] {
+            counter.add(value, if value == "d" { 1 } else { 2 });
+        }
+        let names = |top: Vec<TopValue>| top.into_iter().map(|v| v.value).collect::<Vec<_>>();
+        assert_eq!(names(counter.top(2)), ["a", "b"]);
+        assert_eq!(names(counter.top(10)), ["a", "b", "c", "d"]);
+
+        let counts = ["c", "b", "a"].map(|value| (value.to_owned(), 2));
+        let summary = SpaceSaving::from_counts(2, counts);
+        assert_eq!(summary.top(10), [value("a", 2, 0), value("b", 2, 0)]);
+
+        // The value evicted on a tie is the first alphabetically
+        let mut summary = SpaceSaving::new(2);
+        for value in ["b", "a", "c"] {
+            summary.add(value, 1);
+        }
+        assert_eq!(summary.top(10), [value("c", 2, 1), value("b", 1, 0)]);
+    }
+
+    #[test]
+    fn ignores_zero_weights() {
+        let mut summary = SpaceSaving::new(1);
+        summary.add("a", 0);
+        assert_eq!(summary.top(10), []);
+        summary.add("a", 1);
+        summary.add("b", 0);
+        assert_eq!(summary.top(10), [value("a", 1, 0)]);
+
+        let mut counter = TopCounter::new(Some(1));
+        counter.add("a", 1);
+        counter.add("b", 0);
+        counter.add("a", 0);
+        assert!(matches!(counter.counts, Counts::Exact(_)));
+        assert_eq!(counter.top(10), [value("a", 1, 0)]);
+    }
+
+    #[test]
+    fn counts_saturate() {
+        let mut counter = TopCounter::new(Some(1));
+        counter.add("a", u64::MAX);
+        counter.add("a", 1);
+        let mut other = counter.new_like();
+        other.add("a", u64::MAX);
+        counter.merge(other);
+        assert_eq!(counter.top(1), [value("a", u64::MAX, 0)]);
+
+        let mut summary = SpaceSaving::new(1);
+        summary.add("c", u64::MAX);
+        summary.merge(&SpaceSaving::from_counts(1, [("d".to_owned(), u64::MAX)]));
+        assert_eq!(summary.top(1), [value("c", u64::MAX, u64::MAX)]);
+    }
+
+    #[test]
+    fn counts_exactly_until_full() {
+        let mut counter = TopCounter::new(Some(2));
+        counter.add("a", 5);
+        counter.add("b", 1);
+        assert!(matches!(counter.counts, Counts::Exact(_)));
+        counter.add("c", 2);
+        assert!(matches!(counter.counts, Counts::Approximate(_)));
+        assert_eq!(counter.top(10), [value("a", 5, 0), value("c", 2, 0)]);
+        counter.add("d", 1);
+        assert_eq!(counter.top(10), [value("a", 5, 0), value("d", 3, 2)]);
+    }
+
+    #[test]
+    fn merges_exact_and_approximate_counters() {
+        let stream = stream(10_000, 7);
+        let (first, second) = stream.split_at(2_500);
+        let all = totals(&stream);
+        let counter = |lines: &[(String, u64)], capacity| {
+            let mut counter = TopCounter::new(capacity);
+            lines
+                .iter()
+                .for_each(|(value, weight)| counter.add(value, *weight));
+            counter
+        };
+
+        let mut exact = counter(first, None);
+        exact.merge(counter(second, None));
+        let mut expected = all
+            .iter()
+            .map(|(v, count)| value(v, *count, 0))
+            .collect::<Vec<_>>();
+        expected.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        assert_eq!(exact.top(usize::MAX), expected);
+
+        // Counters over the whole stream are approximate, merged in either order
+        for capacity in [10, 20] {
+            for (first_capacity, second_capacity) in [
+                (Some(capacity), Some(capacity)),
+                (Some(capacity), None),
+                (None, Some(capacity)),
+            ] {
+                for (first, second) in [(first, second), (second, first)] {
+                    let mut merged = counter(first, first_capacity);
+                    merged.merge(counter(second, second_capacity));
+                    assert!(matches!(merged.counts, Counts::Approximate(_)));
+                    assert_bounded(&merged.top(capacity), &all, capacity);
+                }
+            }
+
+            // A counter of ten lines is still exact
+            let mut exact = counter(&first[..10], Some(10));
+            assert!(matches!(exact.counts, Counts::Exact(_)));
+            exact.merge(counter(second, Some(capacity)));
+            let truth = totals(&[&first[..10], second].concat());
+            assert_bounded(&exact.top(capacity), &truth, capacity);
+
+            let mut approximate = counter(first, Some(capacity));
+            approximate.merge(counter(&second[..10], Some(10)));
+            let truth = totals(&[first, &second[..10]].concat());
+            assert_bounded(&approximate.top(capacity), &truth, capacity);
+        }
+    }
+}